@@ -0,0 +1,86 @@
+use crate::movegen::Position;
+use crate::movepick::{evaluate_board, search_position, SearchParams};
+use crate::send;
+use crate::tt::TT;
+use chess::Board;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// Depth of the bench search when none is given
+pub const BENCH_DEPTH: i32 = 4;
+
+// Mix of opening, middlegame and endgame positions used for benchmarking
+pub const BENCH_FENS: [&str; 20] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "rq3rk1/ppp2ppp/1bnpb3/3N2B1/3NP3/7P/PPPQ1PP1/2KR3R w - - 7 14",
+    "r1bq1r1k/1pp1n1pp/1p1p4/4p2Q/4Pp2/1BNP4/PPP2PPP/3R1RK1 w - - 2 14",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "r1bbk1nr/pp3p1p/2n5/1N4p1/2Np1B2/8/PPP2PPP/2KR1B1R w kq - 0 13",
+    "r1bq1rk1/ppp1nppp/4n3/3p3Q/3P4/1BP1B3/PP1N2PP/R4RK1 w - - 1 16",
+    "4r1k1/r1q2ppp/ppp2n2/4P3/5Rb1/1N1BQ3/PPP3PP/R5K1 w - - 1 17",
+    "2rqkb1r/ppp2p2/2npb1p1/1N1Nn2p/2P1PP2/8/PP2B1PP/R1BQK2R b KQ - 0 11",
+    "r1bq1r1k/b1p1npp1/p2p3p/1p6/3PP3/1B2NN2/PP3PPP/R2Q1RK1 w - - 1 16",
+    "3r1rk1/p5pp/bpp1pp2/8/q1PP1P2/b3P3/P2NQRPP/1R2B1K1 b - - 6 22",
+    "r1q2rk1/2p1bppp/2Pp4/p6b/Q1PNp3/4B3/PP1R1PPP/2K4R w - - 2 18",
+    "4k2r/1pb2ppp/1p2p3/1R1p4/3P4/2r1PN2/P4PPP/1R4K1 b - - 3 22",
+    "3q2k1/pb3p1p/4pbp1/2r5/PpN2N2/1P2P2P/5PP1/Q2R2K1 b - - 4 26",
+    "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/8 b - - 0 1",
+    "8/8/8/8/5kp1/P7/8/1K1N4 w - - 0 1",
+    "8/3k4/8/8/8/4B3/4KB2/2B5 w - - 0 1",
+    "6k1/4pp1p/3p2p1/P1pPb3/R7/1r2P1PP/3B1P2/6K1 w - - 0 1",
+];
+
+// Search every benchmark position to a fixed depth through the normal search
+// and report the nodes. Each position starts from an empty table, so with one
+// thread the node count only changes when the search does, which makes it a
+// signature to compare commits by.
+pub fn bench_search(depth: i32) {
+    let start_time = Instant::now();
+    let mut nodes = 0;
+    for (index, fen) in BENCH_FENS.iter().enumerate() {
+        send!("info string bench position {}: {}", index + 1, fen);
+        let mut position = Position::from_fen_or_default(fen);
+        let mut params = SearchParams {
+            max_depth: depth,
+            max_time: Duration::MAX, // fixed depth, the clock must not change the node count
+            ..SearchParams::default()
+        };
+        TT.clear();
+        search_position(&mut position, &mut params);
+        nodes += params.nodes;
+    }
+    let elapsed = start_time.elapsed();
+
+    send!("Positions        : {}", BENCH_FENS.len());
+    send!("Depth            : {}", depth);
+    send!("Total time (ms)  : {}", elapsed.as_millis());
+    send!("Nodes searched   : {}", nodes);
+    send!(
+        "Nodes/sec        : {:.0}",
+        nodes as f64 / elapsed.as_secs_f64().max(1e-9)
+    );
+}
+
+// Measure evaluate_board throughput by evaluating the benchmark positions in turn
+pub fn bench_eval(positions: usize) {
+    let boards: Vec<Board> = BENCH_FENS
+        .iter()
+        .filter_map(|fen| Board::from_str(fen).ok())
+        .collect();
+
+    let start_time = Instant::now();
+    let mut checksum: i64 = 0;
+    for i in 0..positions {
+        checksum += evaluate_board(&boards[i % boards.len()], 40).centipawns() as i64;
+    }
+    let elapsed = start_time.elapsed();
+
+    let evals_per_second = positions as f64 / elapsed.as_secs_f64().max(1e-9);
+    send!("Evaluations      : {}", positions);
+    send!("Total time (ms)  : {}", elapsed.as_millis());
+    send!("Evaluations/sec  : {:.0}", evals_per_second);
+    send!("Checksum         : {}", checksum);
+}
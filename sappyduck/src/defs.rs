@@ -6,6 +6,8 @@ use lazy_static::lazy_static;
 pub const FEN_START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 pub const PIECE_TYPES: usize = 6;
 pub const SQUARES: usize = 64;
+pub const MAX_PLY: i32 = 128; // hard cap on the search depth from the root, extensions included
+pub const MAX_DEPTH: i32 = 64; // deepest iteration iterative deepening goes to
 
 // Bitboards for files
 pub const FILE_A: BitBoard = BitBoard(0x0101010101010101);
@@ -24,6 +26,20 @@ pub const QUEEN_VALUE_NORMAL: f64 = 9.5;
 pub const QUEEN_VALUE_THRESHOLD_ADVANTAGE: f64 = 9.4;
 pub const QUEEN_VALUE_SECOND_QUEEN: f64 = 8.7;
 
+// Rook against a knight or bishop with no other pieces: the whole eval is scaled
+// down toward a draw, most without pawns, less with up to ROOK_VS_MINOR_FEW_PAWNS
+pub const ROOK_VS_MINOR_PAWNLESS_SCALE: f64 = 0.1;
+pub const ROOK_VS_MINOR_FEW_PAWNS_SCALE: f64 = 0.5;
+pub const ROOK_VS_MINOR_FEW_PAWNS: u32 = 2;
+
+// Queen scope: the value moves by QUEEN_SCOPE_STEP per reachable square away from
+// QUEEN_AVERAGE_SCOPE, plus QUEEN_KING_ZONE_BONUS per square hit next to the enemy
+// king, and the whole adjustment stays within QUEEN_SCOPE_LIMIT
+pub const QUEEN_AVERAGE_SCOPE: i32 = 14;
+pub const QUEEN_SCOPE_STEP: f64 = 0.02;
+pub const QUEEN_KING_ZONE_BONUS: f64 = 0.05;
+pub const QUEEN_SCOPE_LIMIT: f64 = 0.4;
+
 pub const FIRST_ROOK_OPENING: f64 = 5.63;
 pub const FIRST_ROOK_MIDDLEGAME: f64 = 5.73;
 pub const FIRST_ROOK_THRESHOLD: f64 = 5.73;
@@ -49,12 +65,81 @@ pub const PAWN_VALUE_MIDDLEGAME: f64 = 0.8;
 pub const PAWN_VALUE_THRESHOLD: f64 = 0.9;
 pub const PAWN_VALUE_ENDGAME: f64 = 1.0;
 
+// Infinite, only for comparisons in exchanges. It must never be summed into a score.
 pub const KING_VALUE: f64 = f64::INFINITY;
 
+// Material imbalance adjustments, applied on top of the summed piece values
+pub const IMBALANCE_ROOKS_VS_QUEEN: f64 = -2.0; // two rooks are about worth a queen
+pub const IMBALANCE_MINORS_VS_QUEEN: f64 = 0.25; // three minors usually outplay a queen
+pub const IMBALANCE_MINORS_VS_ROOK: f64 = 0.35; // two minors beat rook and pawn
+
+// Positional compensation for being the exchange down (minor piece for a rook)
+pub const EXCHANGE_OUTPOST_COMPENSATION: f64 = 0.4; // knight on a protected outpost
+pub const EXCHANGE_BISHOP_PAIR_COMPENSATION: f64 = 0.3;
+pub const EXCHANGE_PAWN_COMPENSATION: f64 = 0.3; // at least a pawn for it
+
+// Passed pawn with a passed neighbour on an adjacent file, by relative rank. Grows
+// steeply, two connected passers on the 6th can beat a rook.
+pub const CONNECTED_PASSER_BONUS: [f64; 8] = [0.0, 0.05, 0.1, 0.2, 0.4, 0.9, 1.6, 0.0];
+
+// Blockading enemy passed pawns: knights are ideal (tuning::KNIGHT_BLOCKADE_BONUS),
+// bishops and queens poor
+pub const BISHOP_BLOCKADE_PENALTY: f64 = 0.1;
+pub const QUEEN_BLOCKADE_PENALTY: f64 = 0.25;
+
+// Backward pawns (base penalty in tuning::BACKWARD_PAWN_PENALTY), worse on a file
+// the enemy rooks can use
+pub const BACKWARD_PAWN_ROOK_PRESSURE_PENALTY: f64 = 0.15; // semi-open file with an enemy rook on it
+pub const ROOK_ON_BACKWARD_PAWN_BONUS: f64 = 0.1; // on top of the rook's semi-open file bonus
+
+// Advanced pawn that no own pawn can defend and the enemy already attacks
+pub const OVEREXTENDED_PAWN_PENALTY: f64 = 0.2;
+
+// Share of a bishop's or knight's value lost when it has no safe square to go to
+pub const TRAPPED_PIECE_PENALTY: f64 = 0.5;
+
+// Minor pieces on the edge of the board, on top of what the knight tables
+// already take off and halved in the endgame. Knights count the whole edge,
+// with the corners worse still, bishops only the a and h files.
+pub const KNIGHT_RIM_PENALTY: f64 = 0.1;
+pub const KNIGHT_CORNER_PENALTY: f64 = 0.15;
+pub const BISHOP_RIM_PENALTY: f64 = 0.05;
+
+// Opening development: per minor piece off its starting square and per center
+// square (d4/e4/d5/e5) an own pawn stands on or attacks. Only in the opening phase.
+pub const DEVELOPED_MINOR_BONUS: f64 = 0.1;
+pub const CENTER_CONTROL_BONUS: f64 = 0.05;
+
+// Rook lifted to the 3rd or 4th rank with room to swing across, more so when it's
+// on the enemy king's side of the board, against a back rank rook with almost
+// nowhere to go
+pub const ROOK_LIFT_BONUS: f64 = 0.2;
+pub const ROOK_LIFT_MIN_MOBILITY: u32 = 5;
+pub const PASSIVE_ROOK_PENALTY: f64 = 0.1;
+pub const PASSIVE_ROOK_MAX_MOBILITY: u32 = 3;
+
+// Castling rights still held, forfeited by moving the king or the rook
+pub const KINGSIDE_CASTLING_BONUS: f64 = 0.15;
+pub const QUEENSIDE_CASTLING_BONUS: f64 = 0.1;
+
 // Checkmate pattern bonuses
 pub const BACK_RANK_MATE_BONUS: f64 = 5.0;
 pub const SMOTHERED_MATE_BONUS: f64 = 4.0;
 
+// f7/f2 while the enemy king is still on its starting square: per piece bearing on
+// the square, plus a bonus once the attackers outnumber its defenders
+pub const F7_ATTACKER_BONUS: f64 = 0.1;
+pub const F7_WEAK_DEFENSE_BONUS: f64 = 0.2;
+
+// Queen joined by a minor piece in an attack on the king zone. The knight works
+// better with her, it covers the squares she doesn't.
+pub const QUEEN_KNIGHT_ATTACK_BONUS: f64 = 0.2;
+pub const QUEEN_BISHOP_ATTACK_BONUS: f64 = 0.12;
+
+// Share of the second most valuable piece hit by a knight or pawn fork, the one
+// that's likely lost once the other has been saved
+pub const FORK_BONUS_SHARE: f64 = 0.3;
+
 // Game phases
 #[derive(PartialEq)]
 pub enum GamePhase {
@@ -80,6 +165,22 @@ pub fn is_original_position(board: &Board) -> bool {
     initial_queens == 2
 }
 
+// Neither side can ever mate: bare kings, a lone minor piece, or only bishops
+// that all stand on squares of one color
+pub fn is_insufficient_material(board: &Board) -> bool {
+    let kings = *board.pieces(KING);
+    let minors = *board.pieces(KNIGHT) | *board.pieces(BISHOP);
+    let others = *board.combined() & !kings;
+    if others.popcnt() <= 1 && (others & !minors).0 == 0 {
+        return true;
+    }
+
+    // Bishops on one color complex, however many and on whichever side
+    const LIGHT_SQUARES: BitBoard = BitBoard(0x55AA55AA55AA55AA);
+    let bishops = *board.pieces(BISHOP);
+    others == bishops && ((bishops & LIGHT_SQUARES).0 == 0 || (bishops & !LIGHT_SQUARES).0 == 0)
+}
+
 pub fn detect_game_phase(board: &Board, move_count: u32) -> GamePhase {
     if move_count <= OPENING_MOVES {
         return GamePhase::Opening;
@@ -194,6 +295,26 @@ pub const EG_KING_TABLE: [f64; 64] = [
     -0.21, -0.11, -0.28, -0.14, -0.24, -0.43,
 ];
 
+// Tapered evaluation: a phase from the non-pawn material left, PHASE_MAX with
+// all of it on the board down to 0 with none of it. The piece-square tables and
+// piece values are blended between middlegame and endgame by it, so the eval
+// doesn't jump when the queens come off.
+pub const PHASE_MAX: i32 = 256;
+const PHASE_WEIGHTS: [(Piece, i32); 4] = [(KNIGHT, 1), (BISHOP, 1), (ROOK, 2), (QUEEN, 4)];
+const PHASE_MATERIAL: i32 = 24; // the start position's sum of PHASE_WEIGHTS
+
+pub fn material_phase(board: &Board) -> i32 {
+    let material: i32 = PHASE_WEIGHTS
+        .iter()
+        .map(|&(piece, weight)| board.pieces(piece).popcnt() as i32 * weight)
+        .sum();
+    material.min(PHASE_MATERIAL) * PHASE_MAX / PHASE_MATERIAL
+}
+
+pub fn taper(mg: f64, eg: f64, phase: i32) -> f64 {
+    (mg * phase as f64 + eg * (PHASE_MAX - phase) as f64) / PHASE_MAX as f64
+}
+
 // Piece value getters
 pub fn get_pawn_value(phase: &GamePhase) -> f64 {
     match phase {
@@ -237,33 +358,151 @@ pub fn get_rook_value(phase: &GamePhase, is_first_rook: bool) -> f64 {
     }
 }
 
+pub fn get_piece_base_value(piece: Piece, phase: &GamePhase) -> f64 {
+    match (piece, phase) {
+        // Pawn values
+        (Piece::Pawn, GamePhase::Opening) => PAWN_VALUE_OPENING,
+        (Piece::Pawn, GamePhase::Middlegame) => PAWN_VALUE_MIDDLEGAME,
+        (Piece::Pawn, GamePhase::Threshold) => PAWN_VALUE_THRESHOLD,
+        (Piece::Pawn, GamePhase::Endgame) => PAWN_VALUE_ENDGAME,
+
+        // Knight values
+        (Piece::Knight, GamePhase::Opening) => KNIGHT_VALUE_OPENING,
+        (Piece::Knight, GamePhase::Middlegame) => KNIGHT_VALUE_MIDDLEGAME,
+        (Piece::Knight, GamePhase::Threshold) => KNIGHT_VALUE_THRESHOLD,
+        (Piece::Knight, GamePhase::Endgame) => KNIGHT_VALUE_ENDGAME,
+
+        // Bishop values - constant across phases except for pair bonus
+        (Piece::Bishop, _) => BISHOP_VALUE,
+
+        // Rook values (using first rook values as default)
+        (Piece::Rook, GamePhase::Opening) => FIRST_ROOK_OPENING,
+        (Piece::Rook, GamePhase::Middlegame) => FIRST_ROOK_MIDDLEGAME,
+        (Piece::Rook, GamePhase::Threshold) => FIRST_ROOK_THRESHOLD,
+        (Piece::Rook, GamePhase::Endgame) => FIRST_ROOK_ENDGAME,
+
+        // Queen values
+        (Piece::Queen, GamePhase::Opening) => QUEEN_VALUE_NORMAL,
+        (Piece::Queen, GamePhase::Middlegame) => QUEEN_VALUE_NORMAL,
+        (Piece::Queen, GamePhase::Threshold) => QUEEN_VALUE_THRESHOLD_ADVANTAGE,
+        (Piece::Queen, GamePhase::Endgame) => QUEEN_VALUE_NORMAL,
+
+        // King value is always infinity
+        (Piece::King, _) => KING_VALUE,
+    }
+}
+
 // Helper function to flip table indices for black's perspective
 pub fn flip_vertical(sq: usize) -> usize {
     sq ^ 56 // Exclusive OR with 56 (7 * 8) flips between ranks
 }
 
-// Function to get piece square value based on color and game phase
-pub fn get_piece_square_value(piece: Piece, square: usize, color: Color, phase: &GamePhase) -> f64 {
+// Function to get piece square value based on color and the material_phase
+pub fn get_piece_square_value(piece: Piece, square: usize, color: Color, phase: i32) -> f64 {
     let sq = if color == Color::Black {
         flip_vertical(square)
     } else {
         square
     };
 
-    match (piece, phase) {
-        (Piece::Pawn, GamePhase::Middlegame) => MG_PAWN_TABLE[sq],
-        (Piece::Pawn, GamePhase::Endgame) => EG_PAWN_TABLE[sq],
-        (Piece::Knight, GamePhase::Middlegame) => MG_KNIGHT_TABLE[sq],
-        (Piece::Knight, GamePhase::Endgame) => EG_KNIGHT_TABLE[sq],
-        (Piece::Bishop, GamePhase::Middlegame) => MG_BISHOP_TABLE[sq],
-        (Piece::Bishop, GamePhase::Endgame) => EG_BISHOP_TABLE[sq],
-        (Piece::Rook, GamePhase::Middlegame) => MG_ROOK_TABLE[sq],
-        (Piece::Rook, GamePhase::Endgame) => EG_ROOK_TABLE[sq],
-        (Piece::Queen, GamePhase::Middlegame) => MG_QUEEN_TABLE[sq],
-        (Piece::Queen, GamePhase::Endgame) => EG_QUEEN_TABLE[sq],
-        (Piece::King, GamePhase::Middlegame) => MG_KING_TABLE[sq],
-        (Piece::King, GamePhase::Endgame) => EG_KING_TABLE[sq],
-        _ => 0.0,
+    let (mg_table, eg_table) = match piece {
+        Piece::Pawn => (&MG_PAWN_TABLE, &EG_PAWN_TABLE),
+        Piece::Knight => (&MG_KNIGHT_TABLE, &EG_KNIGHT_TABLE),
+        Piece::Bishop => (&MG_BISHOP_TABLE, &EG_BISHOP_TABLE),
+        Piece::Rook => (&MG_ROOK_TABLE, &EG_ROOK_TABLE),
+        Piece::Queen => (&MG_QUEEN_TABLE, &EG_QUEEN_TABLE),
+        Piece::King => (&MG_KING_TABLE, &EG_KING_TABLE),
+    };
+
+    taper(mg_table[sq], eg_table[sq], phase)
+}
+
+// Piece-square table sums per color, kept for the middlegame and endgame tables
+// since the phase can change between the moves that update them. The base piece
+// values are summed the same way, kings left out.
+#[derive(Clone, Copy, Default)]
+pub struct PstScore {
+    pub mg: [f64; 2],
+    pub eg: [f64; 2],
+    pub material_mg: [f64; 2],
+    pub material_eg: [f64; 2],
+}
+
+impl PstScore {
+    // Full recomputation, also the reference for the incremental updates
+    pub fn from_board(board: &Board) -> Self {
+        let mut score = PstScore::default();
+        for color in [Color::White, Color::Black] {
+            for &piece in &[KING, QUEEN, ROOK, BISHOP, KNIGHT, PAWN] {
+                for square in board.pieces(piece) & board.color_combined(color) {
+                    score.add(piece, square.to_index(), color);
+                }
+            }
+        }
+        score
+    }
+
+    // Update the sums for the move that turned `before` into `after`. Works on the
+    // changed squares of each piece bitboard, so captures, en passant, castling and
+    // promotions need no special cases.
+    pub fn update(&mut self, before: &Board, after: &Board) {
+        for color in [Color::White, Color::Black] {
+            for &piece in &[KING, QUEEN, ROOK, BISHOP, KNIGHT, PAWN] {
+                let old = before.pieces(piece) & before.color_combined(color);
+                let new = after.pieces(piece) & after.color_combined(color);
+                let changed = old ^ new;
+                if changed.0 == 0 {
+                    continue;
+                }
+                for square in old & changed {
+                    self.remove(piece, square.to_index(), color);
+                }
+                for square in new & changed {
+                    self.add(piece, square.to_index(), color);
+                }
+            }
+        }
+    }
+
+    fn add(&mut self, piece: Piece, square: usize, color: Color) {
+        let side = color.to_index();
+        self.mg[side] += get_piece_square_value(piece, square, color, PHASE_MAX);
+        self.eg[side] += get_piece_square_value(piece, square, color, 0);
+        if piece != KING {
+            self.material_mg[side] += get_piece_base_value(piece, &GamePhase::Middlegame);
+            self.material_eg[side] += get_piece_base_value(piece, &GamePhase::Endgame);
+        }
+    }
+
+    fn remove(&mut self, piece: Piece, square: usize, color: Color) {
+        let side = color.to_index();
+        self.mg[side] -= get_piece_square_value(piece, square, color, PHASE_MAX);
+        self.eg[side] -= get_piece_square_value(piece, square, color, 0);
+        if piece != KING {
+            self.material_mg[side] -= get_piece_base_value(piece, &GamePhase::Middlegame);
+            self.material_eg[side] -= get_piece_base_value(piece, &GamePhase::Endgame);
+        }
+    }
+
+    // Blended by the material_phase like the single table values
+    pub fn value(&self, color: Color, phase: i32) -> f64 {
+        let side = color.to_index();
+        taper(self.mg[side], self.eg[side], phase)
+    }
+
+    // The base piece values, blended the same way
+    pub fn material(&self, color: Color, phase: i32) -> f64 {
+        let side = color.to_index();
+        taper(self.material_mg[side], self.material_eg[side], phase)
+    }
+
+    // Equal up to rounding, for checking the incremental sums against from_board
+    pub fn matches(&self, other: &PstScore) -> bool {
+        let close = |a: [f64; 2], b: [f64; 2]| (0..2).all(|side| (a[side] - b[side]).abs() < 1e-6);
+        close(self.mg, other.mg)
+            && close(self.eg, other.eg)
+            && close(self.material_mg, other.material_mg)
+            && close(self.material_eg, other.material_eg)
     }
 }
 
@@ -276,11 +515,11 @@ lazy_static! {
             let bb = 1u64 << sq;
             // White pawn attacks
             attacks[Color::White as usize][sq] = BitBoard(
-                ((bb << 7) & !FILE_A.0) | ((bb << 9) & !FILE_H.0)
+                ((bb << 7) & !FILE_H.0) | ((bb << 9) & !FILE_A.0)
             );
             // Black pawn attacks
             attacks[Color::Black as usize][sq] = BitBoard(
-                ((bb >> 7) & !FILE_H.0) | ((bb >> 9) & !FILE_A.0)
+                ((bb >> 7) & !FILE_A.0) | ((bb >> 9) & !FILE_H.0)
             );
         }
         attacks
@@ -384,3 +623,53 @@ lazy_static! {
         masks
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn insufficient(fen: &str) -> bool {
+        is_insufficient_material(&Board::from_str(fen).unwrap())
+    }
+
+    #[test]
+    fn lone_minor_pieces_cannot_mate() {
+        assert!(insufficient("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/1N2K3 b - - 0 1"));
+        assert!(insufficient("1n2k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn bishops_on_one_color_cannot_mate() {
+        // c1, f8 and h6 are all dark squares
+        assert!(insufficient("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(insufficient("4kb2/8/7B/8/8/8/8/2B1K3 w - - 0 1"));
+        // c8 is light
+        assert!(!insufficient("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn pawn_diagonals_stop_at_the_board_edge() {
+        let squares = |color: Color, square: Square| -> Vec<Square> {
+            PAWN_ATTACKS[color as usize][square.to_index()]
+                .into_iter()
+                .collect()
+        };
+        assert_eq!(squares(Color::White, Square::A4), [Square::B5]);
+        assert_eq!(squares(Color::White, Square::H4), [Square::G5]);
+        assert_eq!(squares(Color::Black, Square::A4), [Square::B3]);
+        assert_eq!(squares(Color::Black, Square::H4), [Square::G3]);
+        assert_eq!(squares(Color::White, Square::B2), [Square::A3, Square::C3]);
+    }
+
+    #[test]
+    fn mating_material_is_sufficient() {
+        assert!(!insufficient("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1"));
+        assert!(!insufficient("4kn2/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/NN2K3 w - - 0 1"));
+    }
+}
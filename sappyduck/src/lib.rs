@@ -1,6 +1,13 @@
+pub mod bench;
 pub mod bitboard;
 pub mod defs;
 pub mod movegen;
 pub mod movepick;
+pub mod perft;
+pub mod rng;
+pub mod score;
+pub mod stats;
 pub mod time_control;
+pub mod tt;
+pub mod tuning;
 pub mod uci;
@@ -1,10 +1,17 @@
 extern crate chess;
 
+mod bench;
 mod bitboard;
 mod defs;
 mod movegen;
 mod movepick;
+mod perft;
+mod rng;
+mod score;
+mod stats;
 mod time_control;
+mod tt;
+mod tuning;
 mod uci;
 
 use uci::uci_loop;
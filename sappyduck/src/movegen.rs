@@ -1,36 +1,230 @@
 extern crate chess;
 
-use chess::{Board, MoveGen};
+use crate::defs::{PstScore, FEN_START};
+use chess::{get_rank, BitBoard, Board, ChessMove, MoveGen, Piece};
+use std::fmt;
 use std::str::FromStr;
 
+// Why a FEN or a move couldn't be read, for messages to the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    // field is the FEN field at fault, or "position" when every field reads fine
+    // but the position itself is impossible (no kings, side not to move in check...)
+    InvalidFen { field: &'static str, value: String },
+    MalformedMove(String), // neither long algebraic nor SAN
+    IllegalMove(String),   // readable, but not legal in the position
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::InvalidFen { field, value } => {
+                write!(f, "invalid fen {}: \"{}\"", field, value)
+            }
+            ParseError::MalformedMove(text) => write!(f, "malformed move {}", text),
+            ParseError::IllegalMove(text) => write!(f, "illegal move {}", text),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 #[derive(Clone)]
 pub struct Position {
     pub board: Board,
     pub move_count: u32,
+    pub history: Vec<u64>, // positions since the last pawn move or capture, for repetitions
+    pub pst: PstScore,     // piece-square sums, updated move by move
+    pub halfmove_clock: u32, // plies since the last pawn move or capture
+}
+
+// Plies without a pawn move or capture after which the game is drawn
+pub const FIFTY_MOVE_PLIES: u32 = 100;
+
+// Check each FEN field on its own, so the error can say which one is wrong.
+// The clocks are optional, like the chess crate has them.
+fn check_fen_fields(fen: &str) -> Result<(), ParseError> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let field = |index: usize| fields.get(index).copied().unwrap_or("");
+    let invalid = |field: &'static str, value: &str| ParseError::InvalidFen {
+        field,
+        value: value.to_string(),
+    };
+
+    let placement = field(0);
+    let ranks: Vec<&str> = placement.split('/').collect();
+    let rank_ok = |rank: &&str| {
+        let mut squares = 0;
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => squares += c.to_digit(10).unwrap(),
+                'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => {
+                    squares += 1
+                }
+                _ => return false,
+            }
+        }
+        squares == 8
+    };
+    if ranks.len() != 8 || !ranks.iter().all(rank_ok) {
+        return Err(invalid("piece placement", placement));
+    }
+
+    if !matches!(field(1), "w" | "b") {
+        return Err(invalid("side to move", field(1)));
+    }
+
+    let castling = field(2);
+    if castling != "-" && (castling.is_empty() || !castling.chars().all(|c| "KQkq".contains(c))) {
+        return Err(invalid("castling rights", castling));
+    }
+
+    let en_passant = field(3);
+    let ep_ok = match en_passant.as_bytes() {
+        [b'-'] => true,
+        [file, rank] => (b'a'..=b'h').contains(file) && matches!(rank, b'3' | b'6'),
+        _ => false,
+    };
+    if !ep_ok {
+        return Err(invalid("en passant square", en_passant));
+    }
+
+    for (index, name) in [(4, "halfmove clock"), (5, "fullmove number")] {
+        if let Some(value) = fields.get(index) {
+            if value.parse::<u32>().is_err() {
+                return Err(invalid(name, value));
+            }
+        }
+    }
+
+    Ok(())
 }
 
 impl Position {
-    pub fn from_fen(fen: &str) -> Self {
-        let board = Board::from_str(fen).unwrap_or(Board::default());
+    pub fn from_fen(fen: &str) -> Result<Self, ParseError> {
+        check_fen_fields(fen)?;
+        let board = Board::from_str(fen).map_err(|_| ParseError::InvalidFen {
+            field: "position",
+            value: fen.to_string(),
+        })?;
         // Extract fullmove number from FEN if available
         let move_count = if let Some(parts) = fen.split_whitespace().nth(5) {
             parts.parse().unwrap_or(1) * 2 // Convert fullmove number to half moves
         } else {
             0
         };
-        Position { board, move_count }
+        let halfmove_clock = fen
+            .split_whitespace()
+            .nth(4)
+            .and_then(|clock| clock.parse().ok())
+            .unwrap_or(0);
+        Ok(Position {
+            board,
+            move_count,
+            history: Vec::new(),
+            pst: PstScore::from_board(&board),
+            halfmove_clock,
+        })
+    }
+
+    // For FENs known to be good, or when the start position is a fine fallback
+    pub fn from_fen_or_default(fen: &str) -> Self {
+        Self::from_fen(fen).unwrap_or_else(|_| Self::from_fen(FEN_START).unwrap())
+    }
+
+    // The board's FEN with the clocks filled in from the position, the board
+    // itself doesn't keep them
+    pub fn fen(&self) -> String {
+        let board = self.board.to_string();
+        let fields: Vec<&str> = board.split_whitespace().take(4).collect();
+        format!(
+            "{} {} {}",
+            fields.join(" "),
+            self.halfmove_clock,
+            (self.move_count / 2).max(1)
+        )
     }
 
+    // Doesn't check legality, the search only makes moves it generated.
+    // Input from outside goes through try_make_move.
     pub fn make_move(&mut self, mv: &str) -> bool {
-        if let Ok(chess_move) = mv.parse() {
-            self.board = self.board.make_move_new(chess_move);
-            self.move_count += 1;
+        if let Ok(chess_move) = mv.parse::<ChessMove>() {
+            self.play(chess_move);
             true
         } else {
             false
         }
     }
 
+    // Make a move given in long algebraic notation or SAN, if it's legal
+    pub fn try_make_move(&mut self, text: &str) -> Result<(), ParseError> {
+        let chess_move = self.parse_move(text)?;
+        self.play(chess_move);
+        Ok(())
+    }
+
+    fn play(&mut self, chess_move: ChessMove) {
+        // Pawn moves and captures restart the fifty-move count. No position from
+        // before them can come back, so the history restarts too.
+        let irreversible = self.board.piece_on(chess_move.get_source()) == Some(Piece::Pawn)
+            || self.board.piece_on(chess_move.get_dest()).is_some();
+        if irreversible {
+            self.halfmove_clock = 0;
+            self.history.clear();
+        } else {
+            self.halfmove_clock += 1;
+            self.history.push(self.board.get_hash());
+        }
+        let new_board = self.board.make_move_new(chess_move);
+        self.pst.update(&self.board, &new_board);
+        self.board = new_board;
+        self.move_count += 1;
+    }
+
+    // Read a legal move in long algebraic notation (e2e4) or, failing that, SAN (Nf3)
+    pub fn parse_move(&self, text: &str) -> Result<ChessMove, ParseError> {
+        match ChessMove::from_str(text) {
+            Ok(chess_move) if self.board.legal(chess_move) => Ok(chess_move),
+            Ok(_) => Err(ParseError::IllegalMove(text.to_string())),
+            Err(_) => ChessMove::from_san(&self.board, text)
+                .map_err(|_| ParseError::MalformedMove(text.to_string())),
+        }
+    }
+
+    // Pass the turn, for null-move pruning. None when in check, where it's illegal.
+    // The board clears en passant. move_count stays, no piece moved and the game phase
+    // shouldn't change. History restarts since a repetition across a null move is no draw.
+    pub fn make_null_move(&self) -> Option<Position> {
+        self.board.null_move().map(|board| Position {
+            board,
+            move_count: self.move_count,
+            history: Vec::new(),
+            pst: self.pst,
+            halfmove_clock: self.halfmove_clock,
+        })
+    }
+
+    // Drawn by the fifty-move rule, unless the move that got there was mate
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.halfmove_clock >= FIFTY_MOVE_PLIES
+            && (self.board.checkers().0 == 0 || MoveGen::new_legal(&self.board).len() > 0)
+    }
+
+    // Times the current position occurred earlier in the game or search line.
+    // Only the positions since the last irreversible move with the same side to
+    // move can match, that's every other one of the last halfmove_clock.
+    pub fn repetitions(&self) -> usize {
+        let key = self.board.get_hash();
+        self.history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|&&earlier| earlier == key)
+            .count()
+    }
+
     pub fn generate_legal_moves(&self) -> Vec<String> {
         let mut moves = Vec::new();
         for mv in MoveGen::new_legal(&self.board) {
@@ -46,4 +240,264 @@ impl Position {
             false
         }
     }
+
+    // Captures (including en passant) and queen promotions, for quiescence search.
+    // Underpromotions are left out since they are almost never the best capture.
+    pub fn generate_captures(&self) -> Vec<ChessMove> {
+        let side = self.board.side_to_move();
+        let mut movegen = MoveGen::new_legal(&self.board);
+
+        // Restrict the generator to enemy-occupied squares, plus the en passant target
+        // which is empty (chess stores the square of the pawn that can be taken)
+        let mut targets = *self.board.color_combined(!side);
+        if let Some(ep_dest) = self.board.en_passant().and_then(|sq| sq.forward(side)) {
+            targets |= BitBoard::from_square(ep_dest);
+        }
+        movegen.set_iterator_mask(targets);
+        let mut captures: Vec<ChessMove> = movegen
+            .by_ref()
+            .filter(|mv| matches!(mv.get_promotion(), None | Some(Piece::Queen)))
+            .collect();
+
+        // Quiet queen promotions on the last rank
+        movegen.set_iterator_mask(get_rank(side.to_their_backrank()));
+        captures.extend(movegen.filter(|mv| mv.get_promotion() == Some(Piece::Queen)));
+
+        captures
+    }
+
+    // Quiet moves (no capture or promotion) that give check, so quiescence can
+    // follow forcing lines near its root
+    pub fn generate_checks(&self) -> Vec<ChessMove> {
+        let side = self.board.side_to_move();
+        let mut movegen = MoveGen::new_legal(&self.board);
+        movegen.remove_mask(*self.board.color_combined(!side));
+
+        movegen
+            .filter(|mv| {
+                let is_en_passant = self.board.piece_on(mv.get_source()) == Some(Piece::Pawn)
+                    && mv.get_source().get_file() != mv.get_dest().get_file();
+                mv.get_promotion().is_none() && !is_en_passant
+            })
+            .filter(|mv| self.board.make_move_new(*mv).checkers().popcnt() > 0)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(fen: &str) -> Position {
+        Position::from_fen(fen).unwrap()
+    }
+
+    fn sorted(moves: Vec<ChessMove>) -> Vec<String> {
+        let mut moves: Vec<String> = moves.iter().map(|mv| mv.to_string()).collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn captures_include_en_passant_and_queen_promotions() {
+        // Rxa8 and Rxh8, bxa8=Q and b8=Q but no underpromotions, and exd6 en passant
+        let position = position("r3k2r/1P6/8/3pP3/8/2n5/8/R3K2R w KQkq d6 0 1");
+        assert_eq!(
+            sorted(position.generate_captures()),
+            ["a1a8", "b7a8q", "b7b8q", "e5d6", "h1h8"]
+        );
+    }
+
+    #[test]
+    fn checks_are_quiet_moves_that_give_check() {
+        // Rxa8+ captures and so belongs to generate_captures, Rh8+ is a quiet check
+        let position = position("r3k3/8/8/8/1B6/8/8/R3K2R w KQ - 0 1");
+        let checks = sorted(position.generate_checks());
+        let expected = sorted(
+            MoveGen::new_legal(&position.board)
+                .filter(|mv| position.board.piece_on(mv.get_dest()).is_none())
+                .filter(|mv| position.board.make_move_new(*mv).checkers().popcnt() > 0)
+                .collect(),
+        );
+        assert!(checks.contains(&"h1h8".to_string()));
+        assert!(!checks.contains(&"a1a8".to_string()));
+        assert_eq!(checks, expected);
+    }
+
+    #[test]
+    fn null_move_passes_the_turn() {
+        // The board only keeps en passant when a pawn can take
+        let mut after_e4 = position("4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1");
+        assert!(after_e4.make_move("e2e4"));
+        assert!(after_e4.board.en_passant().is_some());
+
+        let passed = after_e4.make_null_move().unwrap();
+        assert_eq!(passed.board.side_to_move(), !after_e4.board.side_to_move());
+        assert_eq!(passed.board.en_passant(), None);
+        assert_eq!(passed.board.combined(), after_e4.board.combined());
+
+        let in_check = position("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1");
+        assert!(in_check.make_null_move().is_none());
+    }
+
+    #[test]
+    fn position_without_kings_is_an_error() {
+        for fen in ["8/8/8/8/8/8/8/8 w - - 0 1", "8/8/8/8/8/8/8/4K3 w - - 0 1"] {
+            assert_eq!(
+                Position::from_fen(fen).err(),
+                Some(ParseError::InvalidFen {
+                    field: "position",
+                    value: fen.to_string()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn incremental_scores_match_a_recompute() {
+        let mut position = position("r3k3/1P6/8/3p4/4P3/8/8/R3K2R w KQq - 0 1");
+        // Castling, a pawn capture, a rook trade, a promotion with check and a king capture
+        for mv in [
+            "e1g1", "e8d7", "e4d5", "a8a1", "f1a1", "d7d6", "b7b8q", "d6d5",
+        ] {
+            position.try_make_move(mv).unwrap();
+            assert!(
+                position.pst.matches(&PstScore::from_board(&position.board)),
+                "after {}",
+                mv
+            );
+        }
+        // Black is down to the king, which counts for nothing
+        let phase = crate::defs::material_phase(&position.board);
+        assert_eq!(position.pst.material(chess::Color::Black, phase), 0.0);
+    }
+
+    #[test]
+    fn moves_read_as_long_algebraic_or_san() {
+        let start = position(FEN_START);
+        assert_eq!(start.parse_move("Nf3"), start.parse_move("g1f3"));
+        assert_eq!(
+            start.parse_move("e2e5"),
+            Err(ParseError::IllegalMove("e2e5".to_string()))
+        );
+        assert_eq!(
+            start.parse_move("Qh5"),
+            Err(ParseError::MalformedMove("Qh5".to_string()))
+        );
+    }
+
+    // Play the moves one by one, noting how often each new position occurred before
+    fn repetitions_along(moves: &[&str]) -> Vec<usize> {
+        let mut position = position(FEN_START);
+        moves
+            .iter()
+            .map(|mv| {
+                position.try_make_move(mv).unwrap();
+                position.repetitions()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn game_without_repeats_has_no_repetitions() {
+        let counts = repetitions_along(&[
+            "e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "b5a4", "g8f6", "e1g1", "f8e7", "f1e1",
+            "b7b5", "a4b3", "d7d6", "c2c3", "e8g8",
+        ]);
+        assert!(counts.iter().all(|&count| count == 0), "{:?}", counts);
+    }
+
+    #[test]
+    fn third_occurrence_is_the_second_repetition() {
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let counts = repetitions_along(&[shuffle, shuffle].concat());
+        // The start position comes back after four plies and again after eight,
+        // the positions in between come back once
+        assert_eq!(counts, [0, 0, 0, 1, 1, 1, 1, 2]);
+    }
+
+    #[test]
+    fn pawn_move_starts_the_repetitions_over() {
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let counts = repetitions_along(&[&shuffle[..], &["e2e4", "e7e5"], &shuffle].concat());
+        // Nothing from before the pawn moves can come back, the shuffle after them
+        // only repeats its own start
+        assert_eq!(counts, [0, 0, 0, 1, 0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn bad_fen_fields_are_named() {
+        for (fen, field, value) in [
+            (
+                "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "piece placement",
+                "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR",
+            ),
+            ("4k3/8/8/8/8/8/8/4K3 x - - 0 1", "side to move", "x"),
+            ("4k3/8/8/8/8/8/8/4K3 w KX - 0 1", "castling rights", "KX"),
+            ("4k3/8/8/8/8/8/8/4K3 w - e4 0 1", "en passant square", "e4"),
+            ("4k3/8/8/8/8/8/8/4K3 w - - x 1", "halfmove clock", "x"),
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 -1", "fullmove number", "-1"),
+        ] {
+            assert_eq!(
+                Position::from_fen(fen).err(),
+                Some(ParseError::InvalidFen {
+                    field,
+                    value: value.to_string()
+                }),
+                "{}",
+                fen
+            );
+        }
+        // The clocks may be left out
+        assert!(Position::from_fen("4k3/8/8/8/8/8/8/4K3 w - -").is_ok());
+    }
+
+    #[test]
+    fn bad_moves_leave_the_position_alone() {
+        let mut start = position(FEN_START);
+        for (text, error) in [
+            ("e7e5", ParseError::IllegalMove("e7e5".to_string())),
+            ("e2", ParseError::MalformedMove("e2".to_string())),
+            ("Ke2", ParseError::MalformedMove("Ke2".to_string())),
+        ] {
+            assert_eq!(start.try_make_move(text), Err(error));
+        }
+        assert_eq!(start.fen(), FEN_START);
+        assert!(start.history.is_empty());
+    }
+
+    #[test]
+    fn halfmove_clock_runs_until_a_pawn_move_or_capture() {
+        let mut position = position("4k3/4p3/8/8/8/8/8/R3K3 w - - 98 80");
+        assert_eq!(position.halfmove_clock, 98);
+        assert!(!position.is_fifty_move_draw());
+        assert!(position.make_move("a1a7"));
+        assert_eq!(position.halfmove_clock, 99);
+        assert!(position.make_move("e7e6"));
+        assert_eq!(position.halfmove_clock, 0);
+        assert!(position.make_move("a7a8"));
+        assert!(position.make_move("e8e7"));
+        assert!(position.make_move("a8a6"));
+        assert!(position.make_move("e7d7"));
+        assert!(position.make_move("a6e6"));
+        assert_eq!(position.halfmove_clock, 0);
+    }
+
+    #[test]
+    fn history_restarts_at_irreversible_moves() {
+        let mut position = position("4k3/4p3/8/8/8/8/8/R3K3 w - - 0 1");
+        for mv in ["a1a2", "e8d8", "a2a1", "d8e8"] {
+            assert!(position.make_move(mv));
+        }
+        assert_eq!(position.history.len(), 4);
+        assert_eq!(position.repetitions(), 1);
+        assert!(position.make_move("a1a2"));
+        assert!(position.make_move("e7e6"));
+        assert!(position.history.is_empty());
+        for mv in ["a2a1", "e8d8", "a1a2", "d8e8"] {
+            assert!(position.make_move(mv));
+        }
+        assert_eq!(position.repetitions(), 1);
+    }
 }
@@ -1,14 +1,121 @@
 use crate::defs::*;
-use crate::movegen::Position;
-use crate::uci::should_stop;
-use chess::{BitBoard, Board, ChessMove, Color, File, Piece, Rank, Square};
+use crate::movegen::{Position, FIFTY_MOVE_PLIES};
+use crate::rng::{seed, Rng};
+use crate::score::Score;
+use crate::send;
+use crate::stats::record_search;
+use crate::tt::{Bound, TtEntry, TT};
+use crate::tuning;
+use crate::uci::{format_score, is_pondering, should_stop};
+use chess::{
+    get_adjacent_files, get_bishop_moves, get_file, get_king_moves, get_knight_moves,
+    get_pawn_attacks, get_rank, get_rook_moves, BitBoard, Board, ChessMove, Color, File, MoveGen,
+    Piece, Rank, Square,
+};
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::thread;
 use std::time::{Duration, Instant};
 
+// Material and piece-square tables only, set through the FastEval option
+static FAST_EVAL: AtomicBool = AtomicBool::new(false);
+
+pub fn set_fast_eval(enabled: bool) {
+    FAST_EVAL.store(enabled, Ordering::SeqCst);
+}
+
+// QSearchChecks: also try quiet checks at the first quiescence ply
+static QSEARCH_CHECKS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_qsearch_checks(enabled: bool) {
+    QSEARCH_CHECKS.store(enabled, Ordering::SeqCst);
+}
+
+// UCI_AnalyseMode: forward pruning and reductions must stay off while it's set,
+// so analysis scores and PVs aren't skewed by them
+static ANALYSE_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_analyse_mode(enabled: bool) {
+    ANALYSE_MODE.store(enabled, Ordering::SeqCst);
+}
+
+pub fn analyse_mode() -> bool {
+    ANALYSE_MODE.load(Ordering::Relaxed)
+}
+
+// OpeningOrdering: the hand-written center, development and king safety rules
+// in order_moves. They only ever break ties between quiet moves, so they can
+// be switched off to measure what they are worth.
+static OPENING_ORDERING: AtomicBool = AtomicBool::new(true);
+
+pub fn set_opening_ordering(enabled: bool) {
+    OPENING_ORDERING.store(enabled, Ordering::SeqCst);
+}
+
+// NodeStats: after each depth, also report the nodes split between the main
+// search and quiescence, to see what pruning changes buy
+static NODE_STATS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_node_stats(enabled: bool) {
+    NODE_STATS.store(enabled, Ordering::SeqCst);
+}
+
+// Threads: the main search plus Threads - 1 Lazy SMP helpers
+pub const MAX_THREADS: usize = 64;
+static THREADS: AtomicUsize = AtomicUsize::new(1);
+
+pub fn set_threads(threads: usize) {
+    THREADS.store(threads.clamp(1, MAX_THREADS), Ordering::SeqCst);
+}
+
+// MultiPV: how many of the best root moves get a line of their own
+pub const MAX_MULTI_PV: usize = 256;
+static MULTI_PV: AtomicUsize = AtomicUsize::new(1);
+
+pub fn set_multi_pv(lines: usize) {
+    MULTI_PV.store(lines.clamp(1, MAX_MULTI_PV), Ordering::SeqCst);
+}
+
+// Set by the main search when it's done, which ends the helpers with it
+static HELPERS_STOP: AtomicBool = AtomicBool::new(false);
+// Nodes searched by the helpers of the current search, counted in steps of
+// STOP_CHECK_INTERVAL
+static HELPER_NODES: AtomicU64 = AtomicU64::new(0);
+
 pub struct SearchParams {
     pub depth: i32,
-    pub start_time: Instant,
+    pub start_time: Instant,  // when the go command came, for time and nps
+    pub clock_start: Instant, // when max_time starts counting, ponderhit restarts it
     pub max_time: Duration,
-    pub nodes: u64,
+    pub max_depth: i32,
+    pub max_nodes: Option<u64>,
+    pub nodes: u64,        // all nodes of the go command, quiescence included
+    pub qnodes: u64,       // the quiescence share of nodes
+    pub seldepth: i32,     // deepest ply reached
+    pub beta_cutoffs: u64, // nodes that failed high
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub pv_move: Option<String>, // best move of the last completed iteration
+    pub root_history: Vec<u64>,  // positions of the game before the search root
+    pub root_excluded: Vec<String>, // root moves left out, the MultiPV lines already found
+    pub root_moves: Vec<String>, // go searchmoves: the only root moves searched, all if empty
+    pub killers: [[Option<ChessMove>; 2]; MAX_PLY as usize], // quiet cutoff moves per ply, newest first
+    // Cutoff credit of quiet moves by from and to square. Like the killers it
+    // lives only as long as one go command, so every search, and so every new
+    // game, starts from an empty table.
+    pub history: [[i32; 64]; 64],
+    pub qsearch_checks: bool, // also try quiet checks (generate_checks) at the first quiescence ply
+    pub scout_late_moves: bool, // principal variation search, off only to measure what it saves
+    pub pondering: bool,      // ignore max_time until the GUI sends ponderhit
+    pub stopped: bool,        // set once the stop flag or the clock ends the search
+    pub contempt: Score,      // what a draw is worth less than equality to the engine
+    pub thread_id: usize,     // 0 for the main search, helpers count up from 1
+    pub mate_moves: Option<i32>, // go mate: done once a mate in this many moves is found
+}
+
+pub struct SearchResult {
+    pub best_move: Option<ChessMove>,
+    pub score: Score, // from the side to move's perspective
 }
 
 impl Default for SearchParams {
@@ -16,22 +123,158 @@ impl Default for SearchParams {
         SearchParams {
             depth: 0,
             start_time: Instant::now(),
+            clock_start: Instant::now(),
             max_time: Duration::from_secs(5),
+            max_depth: MAX_PLY,
+            max_nodes: None,
             nodes: 0,
+            qnodes: 0,
+            seldepth: 0,
+            beta_cutoffs: 0,
+            tt_probes: 0,
+            tt_hits: 0,
+            pv_move: None,
+            root_history: Vec::new(),
+            root_excluded: Vec::new(),
+            root_moves: Vec::new(),
+            killers: [[None; 2]; MAX_PLY as usize],
+            history: [[0; 64]; 64],
+            qsearch_checks: QSEARCH_CHECKS.load(Ordering::Relaxed),
+            scout_late_moves: true,
+            pondering: false,
+            stopped: false,
+            contempt: Score(tuning::CONTEMPT.get()),
+            thread_id: 0,
+            mate_moves: None,
+        }
+    }
+}
+
+// Nodes between polls of the stop flag and the clock, a power of two. At the
+// engine's node rate this is a few milliseconds, so a deadline is overshot by
+// about that much at most.
+const STOP_CHECK_INTERVAL: u64 = 256;
+
+impl SearchParams {
+    // Refresh `stopped` from the stop command and the clock, and notice ponderhit.
+    // On ponderhit the ponder search simply carries on as a normal timed search,
+    // keeping its nodes and results, with the time budget counted from here.
+    // The reported time and nps still count from the go command.
+    pub fn poll_stop(&mut self) {
+        if self.pondering && !is_pondering() {
+            self.pondering = false;
+            self.clock_start = Instant::now();
+        }
+
+        let out_of_time = !self.pondering && self.clock_start.elapsed() >= self.max_time;
+        let helper_done = self.thread_id > 0 && HELPERS_STOP.load(Ordering::Relaxed);
+        if out_of_time || should_stop() || helper_done {
+            self.stopped = true;
         }
     }
+
+    // Whether the score is a mate as short as go mate asked for
+    fn mate_found(&self, score: Score) -> bool {
+        let moves = score.mate_moves().filter(|&moves| moves > 0);
+        self.mate_moves
+            .is_some_and(|limit| moves.is_some_and(|moves| moves <= limit))
+    }
+
+    // Nodes of every thread, for the main search to report
+    pub fn total_nodes(&self) -> u64 {
+        self.nodes + HELPER_NODES.load(Ordering::Relaxed)
+    }
+
+    // Nodes per second over the whole go so far, every thread included
+    pub fn nps(&self) -> u64 {
+        let micros = self.start_time.elapsed().as_micros().max(1);
+        (self.total_nodes() as u128 * 1_000_000 / micros) as u64
+    }
+
+    // Remember a quiet move that caused a cutoff at this ply, the older killer
+    // makes room unless it's the same move
+    fn store_killer(&mut self, ply: i32, mv: ChessMove) {
+        let slots = &mut self.killers[ply as usize];
+        if slots[0] != Some(mv) {
+            slots[1] = slots[0];
+            slots[0] = Some(mv);
+        }
+    }
+
+    // Credit a quiet cutoff move with depth², deeper cutoffs say more. Once
+    // an entry passes HISTORY_LIMIT the whole table is halved, so it can't
+    // overflow and old cutoffs fade against new ones.
+    fn store_history(&mut self, depth: i32, mv: ChessMove) {
+        let entry = &mut self.history[mv.get_source().to_index()][mv.get_dest().to_index()];
+        *entry += depth * depth;
+        if *entry > HISTORY_LIMIT {
+            self.history
+                .iter_mut()
+                .flatten()
+                .for_each(|value| *value /= 2);
+        }
+    }
+
+    // Count a node and only poll for a stop every STOP_CHECK_INTERVAL nodes,
+    // the atomic load and clock read are too costly to do at every node
+    fn visit_node(&mut self) -> bool {
+        self.nodes += 1;
+        if self
+            .max_nodes
+            .is_some_and(|max_nodes| self.nodes >= max_nodes)
+        {
+            self.stopped = true;
+        }
+        if self.nodes & (STOP_CHECK_INTERVAL - 1) == 0 {
+            if self.thread_id > 0 {
+                HELPER_NODES.fetch_add(STOP_CHECK_INTERVAL, Ordering::Relaxed);
+            }
+            self.poll_stop();
+        }
+        self.stopped
+    }
 }
 
+// Aspiration re-searches of one depth before the window opens fully
+const ASPIRATION_MAX_RESEARCHES: u32 = 4;
+
 // Modify pick_move to use iterative deepening
-pub fn pick_move(position: &mut Position) -> Option<String> {
+pub fn pick_move_typed(position: &mut Position) -> Option<ChessMove> {
     let mut params = SearchParams::default();
+    search_position(position, &mut params).best_move
+}
+
+// Same as pick_move_typed, in long algebraic notation
+pub fn pick_move(position: &mut Position) -> Option<String> {
+    pick_move_typed(position).map(|mv| mv.to_string())
+}
+
+// Iterative deepening driver, also reports the score of the chosen move. Runs
+// with the Lazy SMP helpers when Threads is above 1.
+pub fn search_position(position: &mut Position, params: &mut SearchParams) -> SearchResult {
+    params.start_time = Instant::now();
+    params.clock_start = params.start_time;
+    params.root_history = position.history.clone();
+    TT.new_search();
+    let helpers = prepare_helpers(position, params);
+    run_with_helpers(helpers, || main_search(position, params))
+}
+
+fn main_search(position: &mut Position, params: &mut SearchParams) -> SearchResult {
     let mut best_move = None;
-    let mut best_score = f64::NEG_INFINITY;
-    let max_depth = 1; // Changed from 20 to 1
-    let window_size = 0.5; // Aspiration window size in pawns
+    let mut best_score = -Score::INFINITY;
+    // Deepen until the time, a stop or the depth limit ends the search
+    let max_depth = params.max_depth.min(MAX_DEPTH);
+    let window_size = Score(tuning::ASPIRATION_WINDOW.get());
+    // Analysis always searches the full window, it cares more for exact scores than speed
+    let aspiration_depth = if analyse_mode() {
+        i32::MAX
+    } else {
+        tuning::ASPIRATION_MIN_DEPTH.get()
+    };
 
     // Initial info to GUI
-    println!(
+    send!(
         "info string starting search at position with {} moves",
         position.move_count
     );
@@ -39,115 +282,438 @@ pub fn pick_move(position: &mut Position) -> Option<String> {
     // Get all legal moves at the start
     let legal_moves = position.generate_legal_moves();
     if legal_moves.is_empty() {
-        return None;
+        return SearchResult {
+            best_move: None,
+            score: adjudicate(&position.board),
+        };
     }
 
     // Always have a move ready
-    best_move = legal_moves.first().cloned();
+    best_move = root_moves(position, params).first().cloned();
+    let mut completed_depth = 0;
 
     for depth in 1..=max_depth {
         params.depth = depth;
-        params.start_time = Instant::now();
+        let iteration_start = params.nodes;
 
         // Use aspiration windows for deeper searches
-        let mut alpha = if depth >= 4 {
-            best_score - window_size
-        } else {
-            f64::NEG_INFINITY
-        };
-        let mut beta = if depth >= 4 {
-            best_score + window_size
+        let mut delta = window_size;
+        let (mut alpha, mut beta) = if depth >= aspiration_depth {
+            (best_score - delta, best_score + delta)
         } else {
-            f64::INFINITY
+            (-Score::INFINITY, Score::INFINITY)
         };
+        let mut researches = 0;
 
         let mut research_needed = true;
         while research_needed {
-            let (score, mv) = alpha_beta_search(
-                position,
-                depth,
-                alpha,
-                beta,
-                position.board.side_to_move() == Color::White,
-                &mut params,
-            );
+            let (score, mv) = alpha_beta_search(position, depth, 0, alpha, beta, params);
 
-            if score <= alpha {
-                alpha = f64::NEG_INFINITY;
-                continue;
+            // An interrupted depth only has a partial result, keep the last completed one
+            if params.stopped {
+                break;
             }
-            if score >= beta {
-                beta = f64::INFINITY;
+
+            // Outside the window the score is only a bound. Move just the side that
+            // failed, twice as far each time, and open the window completely after
+            // ASPIRATION_MAX_RESEARCHES, which always settles it.
+            if score <= alpha || score >= beta {
+                researches += 1;
+                delta = delta * 2;
+                if researches >= ASPIRATION_MAX_RESEARCHES {
+                    alpha = -Score::INFINITY;
+                    beta = Score::INFINITY;
+                } else if score <= alpha {
+                    alpha = score - delta;
+                } else {
+                    beta = score + delta;
+                }
                 continue;
             }
 
             research_needed = false;
 
+            // A completed depth is more reliable than a shallower one, even if it scores
+            // lower, so always take its result. This keeps the reported score and pv in sync.
             if mv.is_some() {
-                // Only update if score is better or it's the first move
-                if score > best_score || best_move.is_none() {
-                    best_move = mv;
-                    best_score = score;
-                }
+                best_move = mv;
+                best_score = score;
             }
+            completed_depth = depth;
+            params.pv_move = best_move.clone();
 
-            // Always print info for GUI
-            println!(
-                "info depth {} score cp {} nodes {} time {} pv {}",
-                depth,
-                (best_score * 100.0) as i32,
-                params.nodes,
-                params.start_time.elapsed().as_millis(),
-                best_move.as_ref().unwrap_or(&"(none)".to_string())
-            );
+            // Report the settled score of this depth, never an aspiration fail bound
+            if let Some(mv) = best_move.clone() {
+                let lines = multi_pv_lines(position, depth, (best_score, mv), params);
+                send_lines(position, depth, &lines, params);
+            }
+            if NODE_STATS.load(Ordering::Relaxed) {
+                send!(
+                    "info string nodes total {} iteration {} main {} quiescence {}",
+                    params.nodes,
+                    params.nodes - iteration_start,
+                    params.nodes - params.qnodes,
+                    params.qnodes
+                );
+            }
+        }
+
+        params.poll_stop();
+        if params.stopped || params.mate_found(best_score) {
+            break;
+        }
+    }
+
+    let pv = best_move
+        .as_deref()
+        .map(|mv| principal_variation(position, mv, completed_depth))
+        .unwrap_or_default();
+    record_search(params, completed_depth, pv);
+
+    SearchResult {
+        best_move: best_move.and_then(|mv| mv.parse().ok()),
+        score: best_score,
+    }
+}
+
+// Lazy SMP: the helpers search the same root as the main search, each on its
+// own thread with its own position and search state. They share nothing but
+// the transposition table, and what they store there is all they contribute,
+// their own results are thrown away. Each helper starts with a little noise in
+// its history table so they don't all walk the tree in the same order.
+const HELPER_HISTORY_NOISE: u64 = 8;
+
+pub fn prepare_helpers(
+    position: &Position,
+    params: &SearchParams,
+) -> Vec<(Position, SearchParams)> {
+    (1..THREADS.load(Ordering::Relaxed))
+        .map(|thread_id| {
+            let mut helper = SearchParams {
+                start_time: params.start_time,
+                clock_start: params.clock_start,
+                max_time: params.max_time,
+                max_depth: params.max_depth,
+                pondering: params.pondering,
+                contempt: params.contempt,
+                root_history: params.root_history.clone(),
+                root_moves: params.root_moves.clone(),
+                thread_id,
+                ..SearchParams::default()
+            };
+            let mut rng = Rng::new(seed().wrapping_add(thread_id as u64));
+            helper
+                .history
+                .iter_mut()
+                .flatten()
+                .for_each(|value| *value = rng.below(HELPER_HISTORY_NOISE) as i32);
+            (position.clone(), helper)
+        })
+        .collect()
+}
+
+// Run the main search with the helpers next to it. The helpers stop when the
+// main search returns, and are joined before this does.
+pub fn run_with_helpers<T>(helpers: Vec<(Position, SearchParams)>, main: impl FnOnce() -> T) -> T {
+    HELPERS_STOP.store(false, Ordering::SeqCst);
+    HELPER_NODES.store(0, Ordering::SeqCst);
+    thread::scope(|scope| {
+        for (mut position, mut params) in helpers {
+            scope.spawn(move || helper_search(&mut position, &mut params));
+        }
+        let result = main();
+        HELPERS_STOP.store(true, Ordering::SeqCst);
+        result
+    })
+}
+
+// Iterative deepening with the full window and no output
+fn helper_search(position: &mut Position, params: &mut SearchParams) {
+    for depth in 1..=params.max_depth.min(MAX_DEPTH) {
+        params.depth = depth;
+        alpha_beta_search(
+            position,
+            depth,
+            0,
+            -Score::INFINITY,
+            Score::INFINITY,
+            params,
+        );
+        if params.stopped {
+            break;
         }
+    }
+}
+
+// The legal moves the root searches: only those of go searchmoves when it
+// gave any, and never the MultiPV lines already found
+pub fn root_moves(position: &Position, params: &SearchParams) -> Vec<String> {
+    let mut moves = position.generate_legal_moves();
+    if !params.root_moves.is_empty() {
+        moves.retain(|mv| params.root_moves.contains(mv));
+    }
+    moves.retain(|mv| !params.root_excluded.contains(mv));
+    moves
+}
+
+// The best line of a depth followed by the next MultiPV - 1 best root moves,
+// each found by searching the root again without the moves already listed.
+// Never more lines than legal moves, and a stop keeps the lines finished by then.
+pub fn multi_pv_lines(
+    position: &Position,
+    depth: i32,
+    best: (Score, String),
+    params: &mut SearchParams,
+) -> Vec<(Score, String)> {
+    let wanted = MULTI_PV
+        .load(Ordering::Relaxed)
+        .min(root_moves(position, params).len());
+    let mut lines = vec![best];
+    while lines.len() < wanted {
+        params.root_excluded = lines.iter().map(|(_, mv)| mv.clone()).collect();
+        let (score, mv) = alpha_beta_search(
+            position,
+            depth,
+            0,
+            -Score::INFINITY,
+            Score::INFINITY,
+            params,
+        );
+        params.root_excluded.clear();
+        match mv {
+            Some(mv) if !params.stopped => lines.push((score, mv)),
+            _ => break,
+        }
+    }
+    // The first line is the move that gets played, the others follow best first
+    lines[1..].sort_by_key(|&(score, _)| Reverse(score));
+    lines
+}
 
-        if params.start_time.elapsed() >= params.max_time || should_stop() {
+// The line a root move leads to: after it, the move the table holds for each
+// position reached, while it's legal. At most depth moves, and it ends at a
+// repetition so entries pointing at each other can't make it go round.
+pub fn principal_variation(position: &Position, root_move: &str, depth: i32) -> Vec<String> {
+    let mut line = vec![root_move.to_string()];
+    let mut current = position.clone();
+    if !current.make_move(root_move) {
+        return line;
+    }
+    while line.len() < depth as usize && current.repetitions() == 0 {
+        let next = TT
+            .probe(current.board.get_hash())
+            .and_then(|entry| entry.best_move)
+            .filter(|mv| current.board.legal(*mv));
+        let Some(mv) = next else {
             break;
+        };
+        line.push(mv.to_string());
+        current.make_move(&mv.to_string());
+    }
+    line
+}
+
+pub fn send_lines(
+    position: &Position,
+    depth: i32,
+    lines: &[(Score, String)],
+    params: &SearchParams,
+) {
+    for (index, (score, mv)) in lines.iter().enumerate() {
+        send!(
+            "info depth {} seldepth {} multipv {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+            depth,
+            params.seldepth,
+            index + 1,
+            format_score(*score, position.board.side_to_move()),
+            params.total_nodes(),
+            params.nps(),
+            params.start_time.elapsed().as_millis(),
+            TT.hashfull(),
+            principal_variation(position, mv, depth).join(" ")
+        );
+    }
+}
+
+// One ply and the static eval, no search: for a clock too short to search
+// without losing on time. Mates in one are still found.
+pub fn pick_move_instantly(position: &Position) -> SearchResult {
+    let mut best_move: Option<String> = None;
+    let mut best_score = adjudicate(&position.board);
+    for mv in position.generate_legal_moves() {
+        let mut new_position = position.clone();
+        if !new_position.make_move(&mv) {
+            continue;
         }
+        let score = if MoveGen::new_legal(&new_position.board).len() == 0 {
+            -adjudicate(&new_position.board)
+        } else {
+            -evaluate_position(&new_position)
+        };
+        if best_move.is_none() || score > best_score {
+            best_move = Some(mv);
+            best_score = score;
+        }
+    }
+    SearchResult {
+        best_move: best_move.and_then(|mv| mv.parse().ok()),
+        score: best_score,
+    }
+}
+
+// Score of a drawn position (stalemate, repetition, fifty moves, insufficient material)
+// from the side to move's point of view, so every kind of draw is scored the same way.
+// In the search contempt makes a draw look slightly bad for the engine, which moves
+// at even plies from the root. Adjudicating a real game result uses no contempt.
+pub fn draw_score(contempt: Score, ply: i32) -> Score {
+    if ply % 2 == 0 {
+        -contempt
+    } else {
+        contempt
     }
+}
 
-    best_move
+// Score of a finished game for the side to move, when it has no legal moves left.
+// A stalemate is the game's real result, a plain draw with no contempt.
+pub fn adjudicate(board: &Board) -> Score {
+    if board.checkers().0 != 0 {
+        Score::mated_in(0)
+    } else {
+        Score::DRAW
+    }
 }
 
+// Once tuning::PERPETUAL_SEEK_MARGIN centipawns behind in material the search
+// looks for a perpetual check
+const PERPETUAL_CHECK_BONUS: i32 = 200;
+
+// Killer moves go after the good captures but before the other quiet moves,
+// the newer of the two first
+const KILLER_BONUS: [i32; 2] = [500, 400];
+
+// History scores stay below the killer bonuses
+const HISTORY_LIMIT: i32 = 300;
+
 // Add move ordering function
-fn order_moves(moves: &mut Vec<String>, position: &Position) {
+fn order_moves(moves: &mut Vec<String>, position: &Position, params: &SearchParams, ply: i32) {
+    let killers = &params.killers[ply as usize];
+    let seek_perpetual = material_balance(&position.board, position.board.side_to_move())
+        <= -tuning::PERPETUAL_SEEK_MARGIN.get();
+    let opening_rules = OPENING_ORDERING.load(Ordering::Relaxed);
+
     moves.sort_by_cached_key(|mv| {
         let mut score = 0;
         if let Ok(chess_move) = mv.parse::<ChessMove>() {
             // Prioritize captures based on MVV-LVA (Most Valuable Victim - Least Valuable Attacker)
-            if let Some(captured_piece) = position.board.piece_on(chess_move.get_dest()) {
-                let attacker = position.board.piece_on(chess_move.get_source()).unwrap();
-                score += 10 * get_piece_value(captured_piece) - get_piece_value(attacker);
+            score += mvv_lva(&position.board, chess_move);
+
+            // Quiet moves that refuted a sibling are likely to refute this node too
+            if let Some(slot) = killers
+                .iter()
+                .position(|&killer| killer == Some(chess_move))
+            {
+                score += KILLER_BONUS[slot];
             }
 
-            // Center control bonus
-            let dest = chess_move.get_dest().to_index();
-            if (27..=36).contains(&dest) {
-                score += 50;
+            // Quiet moves that cut off often elsewhere in the tree
+            if is_quiet(&position.board, chess_move) {
+                score += params.history[chess_move.get_source().to_index()]
+                    [chess_move.get_dest().to_index()];
             }
 
-            // Development bonus in opening
-            if position.move_count < 10 {
-                if is_development_move(&position.board, chess_move) {
-                    score += 30;
+            // The rules below add at most 120, well under any capture's MVV-LVA score
+            if opening_rules {
+                // Center control bonus
+                let dest = chess_move.get_dest().to_index();
+                if (27..=36).contains(&dest) {
+                    score += 50;
                 }
-            }
 
-            // King safety consideration
-            if is_king_safety_move(&position.board, chess_move) {
-                score += 40;
+                // Development bonus in opening
+                if position.move_count < 10 {
+                    if is_development_move(&position.board, chess_move) {
+                        score += 30;
+                    }
+                }
+
+                // King safety consideration
+                if is_king_safety_move(&position.board, chess_move) {
+                    score += 40;
+                }
+
+                // Penalty for moving pieces multiple times in opening
+                if position.move_count < 10 && is_repeat_move(&position.board, chess_move) {
+                    score -= 20;
+                }
             }
 
-            // Penalty for moving pieces multiple times in opening
-            if position.move_count < 10 && is_repeat_move(&position.board, chess_move) {
-                score -= 20;
+            // When clearly behind, look at checks first to find a perpetual early
+            if seek_perpetual && gives_check(&position.board, chess_move) {
+                score += PERPETUAL_CHECK_BONUS;
             }
         }
         -score // Negative for descending order
     });
 }
 
+// Neither a capture nor a promotion
+fn is_quiet(board: &Board, mv: ChessMove) -> bool {
+    board.piece_on(mv.get_dest()).is_none() && mv.get_promotion().is_none()
+}
+
+fn gives_check(board: &Board, mv: ChessMove) -> bool {
+    board.make_move_new(mv).checkers().0 != 0
+}
+
+// Material difference for color in centipawns, kings excluded
+fn material_balance(board: &Board, color: Color) -> i32 {
+    [QUEEN, ROOK, BISHOP, KNIGHT, PAWN]
+        .iter()
+        .map(|&piece| {
+            let own = (board.pieces(piece) & board.color_combined(color)).popcnt() as i32;
+            let enemy = (board.pieces(piece) & board.color_combined(!color)).popcnt() as i32;
+            (own - enemy) * get_piece_value(piece)
+        })
+        .sum()
+}
+
+// Capture ordering score, 0 for quiet moves
+fn mvv_lva(board: &Board, mv: ChessMove) -> i32 {
+    match board.piece_on(mv.get_dest()) {
+        Some(captured_piece) => {
+            let attacker = board.piece_on(mv.get_source()).unwrap();
+            10 * get_piece_value(captured_piece) - get_piece_value(attacker)
+        }
+        None => 0,
+    }
+}
+
+// Centipawns the opponent must win with a single capture for a piece to count as hanging
+const HANGING_LOSS: i32 = 200;
+
+// Square of a piece the opponent can win outright after mv: capture it while
+// it's undefended, or trade a cheaper piece for it. Recaptures are judged on the
+// board after mv only, no x-rays or longer exchanges, so this is a rough check.
+pub fn hanging_piece(position: &Position, mv: &str) -> Option<Square> {
+    let mut new_position = position.clone();
+    if !new_position.make_move(mv) {
+        return None;
+    }
+    let board = &new_position.board;
+    let defender = !board.side_to_move();
+
+    new_position
+        .generate_captures()
+        .into_iter()
+        .find_map(|capture| {
+            let victim = get_piece_value(board.piece_on(capture.get_dest())?);
+            let attacker = get_piece_value(board.piece_on(capture.get_source())?);
+            let defended = attackers_of(board, capture.get_dest(), defender).0 != 0;
+            let loss = if defended { victim - attacker } else { victim };
+            (loss >= HANGING_LOSS).then_some(capture.get_dest())
+        })
+}
+
 fn get_piece_value(piece: Piece) -> i32 {
     match piece {
         Piece::Pawn => 100,
@@ -184,68 +750,430 @@ fn is_repeat_move(board: &Board, mv: ChessMove) -> bool {
     }
 }
 
+// Centipawns of material ahead from which the root rewards progress, and the
+// reward, small enough to only decide between near equal moves
+const WINNING_MARGIN: i32 = 300;
+const PROGRESS_BONUS: Score = Score(5);
+
+// Pawn moves and captures can't be undone, so they're progress towards a win
+fn makes_progress(position: &Position, mv: &str) -> bool {
+    let is_pawn_move = match mv.parse::<ChessMove>() {
+        Ok(chess_move) => position.board.piece_on(chess_move.get_source()) == Some(Piece::Pawn),
+        Err(_) => false,
+    };
+    is_pawn_move || position.is_capture(mv)
+}
+
+// Widening of the root window that makes ties between root moves exact
+const ROOT_TIE_MARGIN: Score = Score(1);
+
+// Width of a null window, one centipawn
+const NULL_WINDOW: Score = Score(1);
+
+// Preference among root moves with equal scores, higher is better: the previous
+// iteration's best move, then captures and promotions, then moves toward the center
+fn root_tie_break(position: &Position, mv: &str, params: &SearchParams) -> (bool, bool, i32) {
+    let is_pv_move = params.pv_move.as_deref() == Some(mv);
+    let is_forcing = position.is_capture(mv) || mv.len() == 5;
+    let center_distance = match mv.parse::<ChessMove>() {
+        Ok(chess_move) => {
+            let dest = chess_move.get_dest();
+            let file = dest.get_file().to_index() as i32;
+            let rank = dest.get_rank().to_index() as i32;
+            (2 * file - 7).abs().max((2 * rank - 7).abs())
+        }
+        Err(_) => i32::MAX,
+    };
+    (is_pv_move, is_forcing, -center_distance)
+}
+
+// Plies to take off a late quiet move. Good move ordering puts the moves that
+// matter first, so the later a move and the deeper the search, the less it is
+// likely to matter. Captures, promotions, checks and killers are never reduced,
+// and the reduced search keeps at least one ply. Analysis reduces nothing.
+fn late_move_reduction(
+    position: &Position,
+    mv: &str,
+    depth: i32,
+    index: usize,
+    killers: &[Option<ChessMove>; 2],
+) -> i32 {
+    if depth < tuning::LMR_MIN_DEPTH.get() || index < tuning::LMR_MIN_MOVES.get() as usize {
+        return 0;
+    }
+    let chess_move = match mv.parse::<ChessMove>() {
+        Ok(chess_move) => chess_move,
+        Err(_) => return 0,
+    };
+    if !is_quiet(&position.board, chess_move)
+        || gives_check(&position.board, chess_move)
+        || killers.contains(&Some(chess_move))
+    {
+        return 0;
+    }
+    let reduction = ((depth as f64).ln() * (index as f64).ln() / 2.0) as i32;
+    reduction.clamp(1, depth - 2)
+}
+
+// Negamax alpha-beta: every score, the window included, is from the point of
+// view of the side to move at that node, and a child's score is negated on the
+// way up. The static eval and quiescence already work that way.
 pub fn alpha_beta_search(
     position: &Position,
     depth: i32,
-    mut alpha: f64,
-    mut beta: f64,
-    is_maximizing: bool,
+    ply: i32,
+    mut alpha: Score,
+    mut beta: Score,
     params: &mut SearchParams,
-) -> (f64, Option<String>) {
-    if depth == 0 || should_stop() {
-        return (evaluate_board(&position.board, position.move_count), None);
+) -> (Score, Option<String>) {
+    // Check extension: a node in check is searched a ply deeper so forcing lines
+    // aren't cut off halfway. Only while the line is shorter than twice the
+    // iteration depth, which bounds the extensions a run of checks can add.
+    let in_check = position.board.checkers().0 != 0;
+    let depth = if in_check && ply < 2 * params.depth {
+        depth + 1
+    } else {
+        depth
+    };
+
+    // Resolve captures at the horizon instead of evaluating in the middle of an exchange
+    if depth <= 0 {
+        return (quiescence(position, alpha, beta, ply, 0, params), None);
     }
 
-    let mut moves = position.generate_legal_moves();
-    order_moves(&mut moves, position);
+    params.seldepth = params.seldepth.max(ply);
+
+    // The ply cap bounds recursion whatever extensions do to the remaining depth
+    if params.visit_node() || ply >= MAX_PLY {
+        return (evaluate_position(position), None);
+    }
+
+    // A position repeated below the root is a draw, this is also how perpetual
+    // checks are scored: the losing side tries checks first (see order_moves)
+    // and the winning side avoids lines that end in one since they score a draw.
+    // One earlier occurrence in the search line is enough, the side that could
+    // repeat can repeat again. Positions only seen before the root take two,
+    // as they'd need the real threefold.
+    let before_root = params
+        .root_history
+        .iter()
+        .filter(|&&earlier| earlier == position.board.get_hash())
+        .count();
+    if ply > 0 && (position.repetitions() > before_root || before_root >= 2) {
+        return (draw_score(params.contempt, ply), None);
+    }
+
+    // Also a draw once fifty moves pass without a pawn move or capture, or
+    // when neither side has the material left to mate
+    if ply > 0 && (position.is_fifty_move_draw() || is_insufficient_material(&position.board)) {
+        return (draw_score(params.contempt, ply), None);
+    }
+
+    // Mate-distance pruning: being mated here can't be better for the side to
+    // move than being mated right now, and mating can't come sooner than next
+    // ply. A shorter mate already found elsewhere in the tree makes the window
+    // empty, there's no point looking for a longer one.
+    if ply > 0 {
+        alpha = alpha.max(Score::mated_in(ply));
+        beta = beta.min(Score::mate_in(ply + 1));
+        if alpha >= beta {
+            return (alpha, None);
+        }
+    }
+
+    // Winning fast-track: far ahead in material with nothing to capture and no
+    // check, the outcome won't change below here, so search one ply less. Only
+    // a reduction, the line is still searched, so a swindle can still be seen.
+    let fast_track_margin = tuning::FAST_TRACK_MARGIN.get();
+    let decided = fast_track_margin > 0
+        && !analyse_mode()
+        && ply > 0
+        && depth >= 2
+        && position.board.checkers().0 == 0
+        && material_balance(&position.board, position.board.side_to_move()).abs()
+            >= fast_track_margin
+        && position.generate_captures().is_empty();
+    let depth = if decided { depth - 1 } else { depth };
+
+    // An entry searched at least this deep can settle the node. Not at the root,
+    // which must come back with a move of its own, and in analysis not at PV
+    // nodes either, so the reported lines come from a real search.
+    let key = position.board.get_hash();
+    params.tt_probes += 1;
+    let tt_entry = TT.probe(key);
+    if tt_entry.is_some() {
+        params.tt_hits += 1;
+    }
+    let pv_node = beta - alpha > NULL_WINDOW;
+    if let Some(entry) =
+        tt_entry.filter(|entry| ply > 0 && entry.depth >= depth && !(analyse_mode() && pv_node))
+    {
+        let tt_move = entry.best_move.map(|mv| mv.to_string());
+        let score = Score::from_tt(entry.score, ply);
+        match entry.flag {
+            Bound::Exact => return (score, tt_move),
+            Bound::Lower => alpha = alpha.max(score),
+            Bound::Upper => beta = beta.min(score),
+        }
+        if alpha >= beta {
+            params.beta_cutoffs += 1;
+            return (score, tt_move);
+        }
+    }
+
+    // Null-move pruning: if passing the turn still leaves the side to move beyond
+    // the window after a shallower search, a real move will too. Not in check,
+    // where passing is illegal, and not with only pawns left, where zugzwang
+    // makes passing better than any move. Nor in analysis, where it's pruning.
+    let side = position.board.side_to_move();
+    let pawns_and_kings = *position.board.pieces(PAWN) | *position.board.pieces(KING);
+    let has_pieces = (position.board.color_combined(side) & !pawns_and_kings).popcnt() > 0;
+    if ply > 0
+        && !analyse_mode()
+        && depth >= tuning::NULL_MOVE_MIN_DEPTH.get()
+        && has_pieces
+        && !beta.is_mate()
+    {
+        if let Some(null_position) = position.make_null_move() {
+            // A null window at beta, only whether it fails high matters
+            let (eval, _) = alpha_beta_search(
+                &null_position,
+                depth - 1 - tuning::NULL_MOVE_REDUCTION.get(),
+                ply + 1,
+                -beta,
+                -beta + NULL_WINDOW,
+                params,
+            );
+            if -eval >= beta && !params.stopped {
+                params.beta_cutoffs += 1;
+                return (beta, None);
+            }
+        }
+    }
+
+    // The best move found the last time this position was searched goes first
+    let tt_move = tt_entry.and_then(|entry| entry.best_move.map(|mv| mv.to_string()));
+
+    let mut moves = if ply == 0 {
+        root_moves(position, params)
+    } else {
+        position.generate_legal_moves()
+    };
+    order_moves(&mut moves, position, params, ply);
+    if let Some(index) = tt_move.and_then(|tt_move| moves.iter().position(|mv| *mv == tt_move)) {
+        let mv = moves.remove(index);
+        moves.insert(0, mv);
+    }
     if moves.is_empty() {
         if position.board.checkers().0 != 0 {
-            // If in check with no moves, it's checkmate
-            return (-10000.0 + depth as f64, None);
+            // If in check with no moves, it's checkmate, sooner is worse
+            return (Score::mated_in(ply), None);
         }
         // If not in check with no moves, it's stalemate
-        return (0.0, None);
+        return (draw_score(params.contempt, ply), None);
     }
 
+    let (alpha_orig, beta_orig) = (alpha, beta);
     let mut best_move = None;
-    let mut best_value = if is_maximizing {
-        f64::NEG_INFINITY
-    } else {
-        f64::INFINITY
-    };
+    let mut best_value = -Score::INFINITY;
+    // What the root chooses by, the score plus any progress bonus. The bonus
+    // never leaves the node: the score returned, stored and raising alpha is plain.
+    let mut best_selection = -Score::INFINITY;
 
-    for mv in moves {
+    let winning = ply == 0
+        && material_balance(&position.board, position.board.side_to_move()) >= WINNING_MARGIN;
+
+    for (index, mv) in moves.into_iter().enumerate() {
         let mut new_position = position.clone();
         let mv: String = mv;
         if new_position.make_move(&mv) {
-            let (eval, _) = alpha_beta_search(
-                &new_position,
-                depth - 1,
-                alpha,
-                beta,
-                !is_maximizing,
-                params,
-            );
+            // At the root the window is widened a hair so a move scoring the same
+            // as the best so far gets an exact score instead of a bound
+            let margin = match ply {
+                0 if winning => ROOT_TIE_MARGIN + PROGRESS_BONUS,
+                0 => ROOT_TIE_MARGIN,
+                _ => Score::DRAW,
+            };
+            let reduction = if ply > 0 && !in_check && !analyse_mode() {
+                late_move_reduction(position, &mv, depth, index, &params.killers[ply as usize])
+            } else {
+                0
+            };
+            let search = |depth, alpha: Score, beta: Score, params: &mut SearchParams| {
+                let (eval, _) =
+                    alpha_beta_search(&new_position, depth, ply + 1, -beta, -alpha, params);
+                -eval
+            };
+
+            // Principal variation search: once the first move has set the bound, the
+            // others only have to show they can't beat it, which a null window
+            // answers cheaply. Not at the root, which needs exact scores for its
+            // tie-breaks, nor at nodes already searched with a null window.
+            let full_window = (alpha - margin, beta + margin);
+            let scout =
+                params.scout_late_moves && ply > 0 && index > 0 && beta - alpha > NULL_WINDOW;
+            let window = if scout {
+                (alpha, alpha + NULL_WINDOW)
+            } else {
+                full_window
+            };
+
+            let mut eval = search(depth - 1 - reduction, window.0, window.1, params);
+            // A reduced move that looks better than the best so far gets the full depth
+            if reduction > 0 && eval > alpha {
+                eval = search(depth - 1, window.0, window.1, params);
+            }
+            // and one that passed the null window gets the full window for its score
+            if scout && eval > alpha && eval < beta {
+                eval = search(depth - 1, full_window.0, full_window.1, params);
+            }
+
+            // Once stopped every score below is made up, unwind right away. The
+            // caller throws the unfinished depth away.
+            if params.stopped {
+                break;
+            }
 
-            if is_maximizing && eval > best_value {
+            // When clearly ahead, nudge the root toward moves that change something so
+            // a won position isn't shuffled towards the fifty move rule
+            let selection = if winning && makes_progress(position, &mv) {
+                eval + PROGRESS_BONUS
+            } else {
+                eval
+            };
+
+            // Equal root scores are settled by a fixed preference, not by move order
+            let wins_tie = ply == 0
+                && selection == best_selection
+                && best_move.as_ref().is_some_and(|best: &String| {
+                    root_tie_break(position, &mv, params) > root_tie_break(position, best, params)
+                });
+
+            if selection > best_selection || wins_tie {
+                best_selection = selection;
                 best_value = eval;
                 best_move = Some(mv);
                 alpha = alpha.max(eval);
-            } else if !is_maximizing && eval < best_value {
-                best_value = eval;
-                best_move = Some(mv);
-                beta = beta.min(eval);
             }
 
-            if beta <= alpha {
+            if alpha >= beta {
+                params.beta_cutoffs += 1;
+                let cutoff = best_move
+                    .as_deref()
+                    .and_then(|mv| mv.parse::<ChessMove>().ok());
+                if let Some(cutoff) = cutoff.filter(|mv| is_quiet(&position.board, *mv)) {
+                    params.store_killer(ply, cutoff);
+                    params.store_history(depth, cutoff);
+                }
                 break;
             }
         }
     }
 
+    // A search cut short by the clock or a stop has no reliable score. Nor is one
+    // that may have run into the fifty-move rule: the hash doesn't know the clock,
+    // so the draw would come back for the same position with a fresh clock.
+    let fifty_move_safe = position.halfmove_clock + 2 * (depth.max(0) as u32) < FIFTY_MOVE_PLIES;
+    // A root missing some of its moves has no score of its own to store either.
+    let whole_node = ply > 0 || (params.root_excluded.is_empty() && params.root_moves.is_empty());
+    if !params.stopped && fifty_move_safe && whole_node {
+        let flag = if best_value <= alpha_orig {
+            Bound::Upper
+        } else if best_value >= beta_orig {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        TT.store(TtEntry {
+            key,
+            depth,
+            score: best_value.to_tt(ply),
+            flag,
+            best_move: best_move.as_ref().and_then(|mv| mv.parse().ok()),
+        });
+    }
+
     (best_value, best_move)
 }
 
+// Plies past the horizon in which quiescence searches every check evasion
+const QSEARCH_EVASION_PLIES: i32 = 4;
+
+// Quiescence never goes deeper than this past the horizon, the static eval is
+// returned there. Keeps long capture chains from running down the stack.
+const QSEARCH_MAX_PLIES: i32 = 32;
+
+// Search captures and queen promotions until the position is quiet, with scores
+// from the side to move's point of view. qply counts the plies since the horizon.
+fn quiescence(
+    position: &Position,
+    mut alpha: Score,
+    beta: Score,
+    ply: i32,
+    qply: i32,
+    params: &mut SearchParams,
+) -> Score {
+    params.seldepth = params.seldepth.max(ply);
+    params.qnodes += 1;
+    if params.visit_node() || ply >= MAX_PLY || qply >= QSEARCH_MAX_PLIES {
+        return evaluate_position(position);
+    }
+
+    // Standing pat assumes the side to move could just decline the captures, which
+    // isn't true in check: then every evasion has to be searched, and none is mate.
+    // Past QSEARCH_EVASION_PLIES the tree grows too fast for that, and a check only
+    // rules out standing pat when it's mate.
+    let in_check = position.board.checkers().0 != 0;
+    let mut best_value = -Score::INFINITY;
+    let evasions: Vec<ChessMove> = if in_check {
+        MoveGen::new_legal(&position.board).collect()
+    } else {
+        Vec::new()
+    };
+    if in_check && evasions.is_empty() {
+        return Score::mated_in(ply);
+    }
+
+    let moves = if in_check && qply < QSEARCH_EVASION_PLIES {
+        evasions
+    } else {
+        let stand_pat = evaluate_position(position);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+        best_value = stand_pat;
+
+        let mut moves = position.generate_captures();
+        if params.qsearch_checks && qply == 0 {
+            moves.extend(position.generate_checks());
+        }
+        // Nothing to capture, the position is quiet
+        if moves.is_empty() {
+            return stand_pat;
+        }
+        moves.sort_by_cached_key(|mv| -mvv_lva(&position.board, *mv));
+        moves
+    };
+
+    for mv in moves {
+        let mut new_position = position.clone();
+        if !new_position.make_move(&mv.to_string()) {
+            continue;
+        }
+        let score = -quiescence(&new_position, -beta, -alpha, ply + 1, qply + 1, params);
+
+        best_value = best_value.max(score);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            params.beta_cutoffs += 1;
+            break;
+        }
+    }
+
+    best_value
+}
+
 struct AttackInfo {
     attackers: Vec<(Piece, usize)>, // (piece type, square)
     defenders: Vec<(Piece, usize)>,
@@ -256,8 +1184,9 @@ struct AttackInfo {
 
 struct RookInfo {
     is_first_rook: bool,
-    is_open_file: bool,
-    is_semi_open: bool,
+    is_open_file: bool,          // no pawns of either color on the file
+    is_semi_open: bool,          // no own pawns, but enemy pawns to work against
+    targets_backward_pawn: bool, // semi-open and one of those enemy pawns is backward
     controls_seventh: bool,
 }
 
@@ -265,13 +1194,10 @@ fn analyze_rook_position(board: &Board, square: usize, color: Color) -> RookInfo
     let rook_bb = BitBoard(1 << square);
     let own_pawns = board.pieces(PAWN) & board.color_combined(color);
     let enemy_pawns = board.pieces(PAWN) & board.color_combined(!color);
-    let file_mask = match square % 8 {
-        0 => FILE_A,
-        1 => FILE_B,
-        6 => FILE_G,
-        7 => FILE_H,
-        _ => BitBoard((0x0101010101010101u64) << (square % 8)),
-    };
+    let file_mask = get_file(File::from_index(square % 8));
+    let own_on_file = file_mask & own_pawns;
+    let enemy_on_file = file_mask & enemy_pawns;
+    let is_semi_open = own_on_file.0 == 0 && enemy_on_file.0 != 0;
 
     let seventh_rank = if color == Color::White {
         RANK_7
@@ -281,8 +1207,12 @@ fn analyze_rook_position(board: &Board, square: usize, color: Color) -> RookInfo
 
     RookInfo {
         is_first_rook: true, // Will be adjusted in evaluate_material
-        is_open_file: (file_mask & (own_pawns | enemy_pawns)).0 == 0,
-        is_semi_open: (file_mask & own_pawns).0 == 0,
+        is_open_file: (own_on_file | enemy_on_file).0 == 0,
+        is_semi_open,
+        targets_backward_pawn: is_semi_open
+            && enemy_on_file
+                .into_iter()
+                .any(|sq| is_backward_pawn(board, sq, !color)),
         controls_seventh: (rook_bb & seventh_rank).0 != 0,
     }
 }
@@ -294,6 +1224,9 @@ fn get_rook_position_bonus(info: &RookInfo) -> f64 {
         bonus += 0.3;
     } else if info.is_semi_open {
         bonus += 0.15;
+        if info.targets_backward_pawn {
+            bonus += ROOK_ON_BACKWARD_PAWN_BONUS;
+        }
     }
 
     if info.controls_seventh {
@@ -303,40 +1236,6 @@ fn get_rook_position_bonus(info: &RookInfo) -> f64 {
     bonus
 }
 
-fn get_piece_base_value(piece: Piece, phase: &GamePhase) -> f64 {
-    match (piece, phase) {
-        // Pawn values
-        (Piece::Pawn, GamePhase::Opening) => PAWN_VALUE_OPENING,
-        (Piece::Pawn, GamePhase::Middlegame) => PAWN_VALUE_MIDDLEGAME,
-        (Piece::Pawn, GamePhase::Threshold) => PAWN_VALUE_THRESHOLD,
-        (Piece::Pawn, GamePhase::Endgame) => PAWN_VALUE_ENDGAME,
-
-        // Knight values
-        (Piece::Knight, GamePhase::Opening) => KNIGHT_VALUE_OPENING,
-        (Piece::Knight, GamePhase::Middlegame) => KNIGHT_VALUE_MIDDLEGAME,
-        (Piece::Knight, GamePhase::Threshold) => KNIGHT_VALUE_THRESHOLD,
-        (Piece::Knight, GamePhase::Endgame) => KNIGHT_VALUE_ENDGAME,
-
-        // Bishop values - constant across phases except for pair bonus
-        (Piece::Bishop, _) => BISHOP_VALUE,
-
-        // Rook values (using first rook values as default)
-        (Piece::Rook, GamePhase::Opening) => FIRST_ROOK_OPENING,
-        (Piece::Rook, GamePhase::Middlegame) => FIRST_ROOK_MIDDLEGAME,
-        (Piece::Rook, GamePhase::Threshold) => FIRST_ROOK_THRESHOLD,
-        (Piece::Rook, GamePhase::Endgame) => FIRST_ROOK_ENDGAME,
-
-        // Queen values
-        (Piece::Queen, GamePhase::Opening) => QUEEN_VALUE_NORMAL,
-        (Piece::Queen, GamePhase::Middlegame) => QUEEN_VALUE_NORMAL,
-        (Piece::Queen, GamePhase::Threshold) => QUEEN_VALUE_THRESHOLD_ADVANTAGE,
-        (Piece::Queen, GamePhase::Endgame) => QUEEN_VALUE_NORMAL,
-
-        // King value is always infinity
-        (Piece::King, _) => KING_VALUE,
-    }
-}
-
 fn evaluate_square_control(board: &Board, square: usize, color: Color) -> AttackInfo {
     let mut info = AttackInfo {
         attackers: Vec::new(),
@@ -473,6 +1372,100 @@ fn get_piece_value_on_square(board: &Board, square: usize) -> f64 {
     0.0
 }
 
+// Bonus when one of color's queens and a knight or bishop both hit the enemy
+// king zone, the knight pairing counted first. Fades out toward the endgame.
+fn evaluate_queen_partners(board: &Board, color: Color, phase: &GamePhase) -> f64 {
+    let scale = match phase {
+        GamePhase::Opening | GamePhase::Middlegame => 1.0,
+        GamePhase::Threshold => 0.5,
+        GamePhase::Endgame => return 0.0,
+    };
+    let own_pieces = board.color_combined(color);
+    let occupied = *board.combined();
+    let enemy_king = board.king_square(!color);
+    let king_zone = get_king_moves(enemy_king) | BitBoard::from_square(enemy_king);
+    let hits_zone = |attacks: BitBoard| (attacks & king_zone).0 != 0;
+
+    let queen_attacks = (board.pieces(QUEEN) & own_pieces)
+        .into_iter()
+        .any(|sq| hits_zone(get_bishop_moves(sq, occupied) | get_rook_moves(sq, occupied)));
+    if !queen_attacks {
+        return 0.0;
+    }
+
+    let knight_attacks = (board.pieces(KNIGHT) & own_pieces)
+        .into_iter()
+        .any(|sq| hits_zone(get_knight_moves(sq)));
+    let bishop_attacks = (board.pieces(BISHOP) & own_pieces)
+        .into_iter()
+        .any(|sq| hits_zone(get_bishop_moves(sq, occupied)));
+
+    if knight_attacks {
+        QUEEN_KNIGHT_ATTACK_BONUS * scale
+    } else if bishop_attacks {
+        QUEEN_BISHOP_ATTACK_BONUS * scale
+    } else {
+        0.0
+    }
+}
+
+// Bonus for color's pieces aiming at the enemy f-pawn square next to its king,
+// only defended by the king at first, as long as the king hasn't moved away
+fn evaluate_f7_attack(board: &Board, color: Color) -> f64 {
+    let (king_home, target) = match color {
+        Color::White => (Square::E8, Square::F7),
+        Color::Black => (Square::E1, Square::F2),
+    };
+    if board.king_square(!color) != king_home {
+        return 0.0;
+    }
+
+    let attackers = attackers_of(board, target, color).popcnt();
+    let defenders = attackers_of(board, target, !color).popcnt();
+    let mut value = attackers as f64 * F7_ATTACKER_BONUS;
+    if attackers > defenders {
+        value += F7_WEAK_DEFENSE_BONUS;
+    }
+    value
+}
+
+// Bonus for color's knights and pawns attacking two enemy pieces at once, where
+// each of them is the king, worth more than the forking piece or undefended,
+// so the fork wins something even though one of them gets away
+fn evaluate_forks(board: &Board, color: Color, phase: &GamePhase) -> f64 {
+    let own_pieces = board.color_combined(color);
+    let enemy_pieces = board.color_combined(!color) & !board.pieces(PAWN);
+    let mut value = 0.0;
+
+    for piece in [KNIGHT, PAWN] {
+        let forker_value = get_piece_base_value(piece, phase);
+        for square in board.pieces(piece) & own_pieces {
+            let attacks = match piece {
+                Piece::Knight => get_knight_moves(square),
+                _ => get_pawn_attacks(square, color, BitBoard(!0)),
+            };
+
+            let mut targets: Vec<f64> = (attacks & enemy_pieces)
+                .into_iter()
+                .filter_map(|target| {
+                    // The king's value is infinite, but the sort below puts it first
+                    // and only the second target's value gets used
+                    let target_value = get_piece_base_value(board.piece_on(target)?, phase);
+                    let undefended = attackers_of(board, target, !color).0 == 0;
+                    (target_value > forker_value || undefended).then_some(target_value)
+                })
+                .collect();
+
+            if targets.len() >= 2 {
+                targets.sort_by(|a, b| b.total_cmp(a));
+                value += targets[1] * FORK_BONUS_SHARE;
+            }
+        }
+    }
+
+    value
+}
+
 fn detect_checkmate_patterns(board: &Board, color: Color) -> f64 {
     let mut pattern_value = 0.0;
 
@@ -538,53 +1531,298 @@ fn detect_smothered_mate(board: &Board, king_sq: Square, king_color: Color) -> b
     KNIGHT_ATTACKS[king_sq.to_index()].0 & enemy_knights.0 != 0
 }
 
-pub fn evaluate_board(board: &Board, move_count: u32) -> f64 {
-    let mut white_value = 0.0;
-    let mut black_value = 0.0;
+// Evaluate a bare board, summing the piece-square tables from scratch
+pub fn evaluate_board(board: &Board, move_count: u32) -> Score {
+    Score::from_pawns(evaluate_with_pst(
+        board,
+        move_count,
+        &PstScore::from_board(board),
+    ))
+}
+
+// Evaluate a search position with its incrementally kept piece-square sums
+pub fn evaluate_position(position: &Position) -> Score {
+    debug_assert!({
+        let scratch = PstScore::from_board(&position.board);
+        scratch.matches(&position.pst)
+    });
+    Score::from_pawns(evaluate_with_pst(
+        &position.board,
+        position.move_count,
+        &position.pst,
+    ))
+}
+
+fn evaluate_with_pst(board: &Board, move_count: u32, pst: &PstScore) -> f64 {
+    // The chess crate refuses boards without both kings, but several terms below
+    // assume them, so don't risk garbage if one ever slips through
+    if board.pieces(KING).popcnt() != 2 {
+        return 0.0;
+    }
+
+    if is_insufficient_material(board) || is_dead_position(board) {
+        return 0.0;
+    }
+
+    let terms = evaluate_terms(board, move_count, pst);
+    // FastEval is material and piece-square tables only, without scaling either
+    let scale = if FAST_EVAL.load(Ordering::Relaxed) {
+        1.0
+    } else {
+        drawish_endgame_scale(board)
+    };
+    from_side_to_move(
+        board,
+        terms.total(Color::White) * scale,
+        terms.total(Color::Black) * scale,
+    )
+}
+
+// The evaluation of one side in the groups the eval command reports, each
+// already weighted, so they add up to what the search sees
+#[derive(Default)]
+pub struct EvalTerms {
+    pub piece_square: [f64; 2],
+    pub material: [f64; 2], // piece values and imbalances
+    pub pawn_structure: [f64; 2],
+    pub attacks: [f64; 2],
+    pub checkmate_patterns: [f64; 2],
+    pub king_attack: [f64; 2], // f7 pressure, queen partners, forks
+    pub other: [f64; 2],       // piece placement, development, castling, rooks
+}
+
+impl EvalTerms {
+    pub fn total(&self, color: Color) -> f64 {
+        let side = color.to_index();
+        self.piece_square[side]
+            + self.material[side]
+            + self.pawn_structure[side]
+            + self.attacks[side]
+            + self.checkmate_patterns[side]
+            + self.king_attack[side]
+            + self.other[side]
+    }
+}
+
+fn evaluate_terms(board: &Board, move_count: u32, pst: &PstScore) -> EvalTerms {
     let phase = detect_game_phase(board, move_count);
+    let material_phase = material_phase(board);
+    let mut terms = EvalTerms::default();
+    let material_weight = tuning::MATERIAL_WEIGHT.factor();
+    let pawn_weight = tuning::PAWN_STRUCTURE_WEIGHT.factor();
+    let attack_weight = tuning::KING_ATTACK_WEIGHT.factor();
+
+    for color in [Color::White, Color::Black] {
+        // Add positional values for each piece
+        terms.piece_square[color.to_index()] = pst.value(color, material_phase);
+    }
 
-    // Add positional values for each piece
-    for square in 0..64 {
-        let sq_bb = BitBoard(1 << square);
-
-        // For each piece type on this square
-        for &piece in &[KING, QUEEN, ROOK, BISHOP, KNIGHT, PAWN] {
-            let piece_bb = board.pieces(piece);
-            if (piece_bb & sq_bb).0 != 0 {
-                if (board.color_combined(Color::White) & sq_bb).0 != 0 {
-                    white_value += get_piece_square_value(piece, square, Color::White, &phase);
-                } else if (board.color_combined(Color::Black) & sq_bb).0 != 0 {
-                    black_value += get_piece_square_value(piece, square, Color::Black, &phase);
-                }
+    // Trade accuracy for speed at very fast time controls: the bare piece
+    // values, none of the bonuses that come with them
+    if FAST_EVAL.load(Ordering::Relaxed) {
+        for color in [Color::White, Color::Black] {
+            terms.material[color.to_index()] = pst.material(color, material_phase);
+        }
+        return terms;
+    }
+
+    for color in [Color::White, Color::Black] {
+        let side = color.to_index();
+
+        // Add material values and bonuses, weighted by the playing style like the
+        // pawn structure and king attack groups below
+        terms.material[side] =
+            material_weight * evaluate_material(board, color, &phase, material_phase);
+
+        // Correct for piece combinations that aren't worth their summed values
+        terms.material[side] += material_weight * evaluate_imbalance(board, color);
+
+        // Add backward pawns and the rooks attacking them, overextended pawns,
+        // connected passed pawns and blockades of passed pawns
+        let pawns = evaluate_backward_pawns(board, color)
+            + evaluate_overextension(board, color)
+            + evaluate_connected_passers(board, color)
+            + evaluate_blockades(board, color);
+        terms.pawn_structure[side] = pawn_weight * pawns;
+
+        // Add trapped minor pieces
+        let mut other = evaluate_trapped_pieces(board, color);
+
+        // Add development, it stops mattering once the opening is over
+        if phase == GamePhase::Opening {
+            other += evaluate_development(board, color);
+        }
+
+        // Add minor pieces sitting on the rim
+        other += evaluate_rim_pieces(board, color, &phase);
+
+        // Add castling rights and rook activity, they only matter while there's a
+        // middlegame to play
+        if phase != GamePhase::Endgame {
+            other += evaluate_castling_rights(board, color);
+            other += evaluate_rook_activity(board, color);
+        }
+        terms.other[side] = other;
+
+        // Add attack evaluation
+        let attacks: f64 = (0..64)
+            .map(|square| evaluate_attacks(board, square, color))
+            .sum();
+        terms.attacks[side] = attack_weight * attacks;
+
+        // Add checkmate pattern detection
+        terms.checkmate_patterns[side] = attack_weight * detect_checkmate_patterns(board, color);
+
+        // Add pressure on f7/f2 next to an uncastled king, queen and minor piece
+        // teamwork against the king, and knight and pawn forks
+        let king_attack = evaluate_f7_attack(board, color)
+            + evaluate_queen_partners(board, color, &phase)
+            + evaluate_forks(board, color, &phase);
+        terms.king_attack[side] = attack_weight * king_attack;
+    }
+
+    terms
+}
+
+// The eval command: every group for both sides, then the total. Read from
+// the side to move like the search, the same value evaluate_board returns.
+pub fn eval_report(position: &Position) {
+    let board = &position.board;
+    let terms = evaluate_terms(board, position.move_count, &position.pst);
+    let groups = [
+        ("Material", terms.material),
+        ("Piece-square", terms.piece_square),
+        ("Pawn structure", terms.pawn_structure),
+        ("Attacks", terms.attacks),
+        ("Checkmate patterns", terms.checkmate_patterns),
+        ("King attack", terms.king_attack),
+        ("Other", terms.other),
+    ];
+    send!("{:<20} {:>8} {:>8}", "Term", "White", "Black");
+    for (name, values) in groups {
+        send!("{:<20} {:>8.2} {:>8.2}", name, values[0], values[1]);
+    }
+    send!(
+        "{:<20} {:>8.2} {:>8.2}",
+        "Sum",
+        terms.total(Color::White),
+        terms.total(Color::Black)
+    );
+    if is_insufficient_material(board) || is_dead_position(board) {
+        send!("Drawn on material, the total is 0");
+    }
+    send!(
+        "Total (side to move): {} cp",
+        evaluate_position(position).centipawns()
+    );
+}
+
+// Kings and pawns only, every pawn stuck behind an enemy pawn with nothing to
+// capture, and neither king able to get next to an enemy pawn without walking
+// through a pawn or a square a pawn guards. Nothing can ever change, so it's a
+// draw. Kept strict on purpose: a king that can merely touch a defended pawn
+// already makes the position count as alive.
+fn is_dead_position(board: &Board) -> bool {
+    let pawns = *board.pieces(PAWN);
+    if *board.combined() != pawns | board.pieces(KING) || pawns.0 == 0 {
+        return false;
+    }
+
+    for color in [Color::White, Color::Black] {
+        let own_pawns = pawns & board.color_combined(color);
+        let enemy_pawns = pawns & board.color_combined(!color);
+        for square in own_pawns {
+            let blocked = square
+                .forward(color)
+                .is_some_and(|ahead| (enemy_pawns & BitBoard::from_square(ahead)).0 != 0);
+            if !blocked || (get_pawn_attacks(square, color, enemy_pawns)).0 != 0 {
+                return false;
+            }
+        }
+
+        // Squares the king can walk to, flooded one king step at a time
+        let allowed = !pawns & !pawn_attacks(board, !color);
+        let mut reach = BitBoard::from_square(board.king_square(color));
+        loop {
+            let next = reach
+                .into_iter()
+                .fold(reach, |acc, sq| acc | (get_king_moves(sq) & allowed));
+            if next == reach {
+                break;
             }
+            reach = next;
+        }
+        let touches_pawn = enemy_pawns
+            .into_iter()
+            .any(|sq| (get_king_moves(sq) & reach).0 != 0);
+        if touches_pawn {
+            return false;
         }
     }
 
-    // Add material values and bonuses
-    white_value += evaluate_material(board, Color::White, &phase);
-    black_value += evaluate_material(board, Color::Black, &phase);
+    true
+}
 
-    // Add attack evaluation
-    for square in 0..64 {
-        white_value += evaluate_attacks(board, square, Color::White);
-        black_value += evaluate_attacks(board, square, Color::Black);
+// Factor for endgames that tend to be drawn despite the material, 1.0 otherwise.
+// Covers a lone rook against a lone minor piece, each side's pawns aside.
+fn drawish_endgame_scale(board: &Board) -> f64 {
+    if board.pieces(QUEEN).0 != 0 {
+        return 1.0;
+    }
+    let count =
+        |piece: Piece, color: Color| (board.pieces(piece) & board.color_combined(color)).popcnt();
+    let minors = |color: Color| count(KNIGHT, color) + count(BISHOP, color);
+    let rook_vs_minor = |color: Color| {
+        count(ROOK, color) == 1
+            && minors(color) == 0
+            && count(ROOK, !color) == 0
+            && minors(!color) == 1
+    };
+    if !rook_vs_minor(Color::White) && !rook_vs_minor(Color::Black) {
+        return 1.0;
     }
 
-    // Add checkmate pattern detection
-    white_value += detect_checkmate_patterns(board, Color::White);
-    black_value += detect_checkmate_patterns(board, Color::Black);
+    match board.pieces(PAWN).popcnt() {
+        0 => ROOK_VS_MINOR_PAWNLESS_SCALE,
+        pawns if pawns <= ROOK_VS_MINOR_FEW_PAWNS => ROOK_VS_MINOR_FEW_PAWNS_SCALE,
+        _ => 1.0,
+    }
+}
 
-    // Modify the final evaluation to be from the perspective of the side to move
+// Modify the final evaluation, already scaled, to be from the perspective of the side to move.
+// The result is kept finite and within tuning::EVAL_LIMIT, clear of the mate
+// scores, so a stray infinity or NaN from some term can't break alpha-beta.
+fn from_side_to_move(board: &Board, white_value: f64, black_value: f64) -> f64 {
     let score = match board.side_to_move() {
         Color::White => white_value - black_value,
         Color::Black => black_value - white_value,
     };
-
-    score
+    if score.is_nan() {
+        return 0.0;
+    }
+    let limit = tuning::EVAL_LIMIT.pawns();
+    score.clamp(-limit, limit)
 }
 
-fn evaluate_material(board: &Board, color: Color, phase: &GamePhase) -> f64 {
+// Piece values are tapered between the middlegame, or the opening while it
+// lasts, and the endgame by the material_phase
+fn evaluate_material(board: &Board, color: Color, phase: &GamePhase, material_phase: i32) -> f64 {
     let mut value = 0.0;
+    let mg_phase = if *phase == GamePhase::Opening {
+        &GamePhase::Opening
+    } else {
+        &GamePhase::Middlegame
+    };
+    let blend = |value: fn(&GamePhase) -> f64| {
+        taper(value(mg_phase), value(&GamePhase::Endgame), material_phase)
+    };
+    let rook_value = |is_first_rook| {
+        taper(
+            get_rook_value(mg_phase, is_first_rook),
+            get_rook_value(&GamePhase::Endgame, is_first_rook),
+            material_phase,
+        )
+    };
 
     // Count piece material
     let piece_counts = [
@@ -604,6 +1842,9 @@ fn evaluate_material(board: &Board, color: Color, phase: &GamePhase) -> f64 {
                 } else if count > 1 {
                     value += QUEEN_VALUE_THRESHOLD_ADVANTAGE + QUEEN_VALUE_SECOND_QUEEN;
                 }
+                for square in bb {
+                    value += queen_scope_adjustment(board, square, color);
+                }
             }
             Piece::Rook => {
                 if count > 0 {
@@ -611,7 +1852,7 @@ fn evaluate_material(board: &Board, color: Color, phase: &GamePhase) -> f64 {
                     let square = bb.0.trailing_zeros() as usize;
                     let mut info = analyze_rook_position(board, square, color);
                     info.is_first_rook = true;
-                    value += get_rook_value(phase, true) + get_rook_position_bonus(&info);
+                    value += rook_value(true) + get_rook_position_bonus(&info);
 
                     if count > 1 {
                         // Process second rook
@@ -619,7 +1860,7 @@ fn evaluate_material(board: &Board, color: Color, phase: &GamePhase) -> f64 {
                         let second_square = second_bb.0.trailing_zeros() as usize;
                         let mut info = analyze_rook_position(board, second_square, color);
                         info.is_first_rook = false;
-                        value += get_rook_value(phase, false) + get_rook_position_bonus(&info);
+                        value += rook_value(false) + get_rook_position_bonus(&info);
 
                         // Add bonus for connected rooks
                         if (ROOK_ATTACKS[square].0 & second_bb.0) != 0 {
@@ -631,14 +1872,1400 @@ fn evaluate_material(board: &Board, color: Color, phase: &GamePhase) -> f64 {
             Piece::Bishop => {
                 value += count as f64 * BISHOP_VALUE;
                 if count >= 2 {
-                    value += get_bishop_pair_bonus(phase);
+                    value += blend(get_bishop_pair_bonus);
                 }
             }
-            Piece::Knight => value += count as f64 * get_knight_value(phase),
-            Piece::Pawn => value += count as f64 * get_pawn_value(phase),
+            Piece::Knight => value += count as f64 * blend(get_knight_value),
+            Piece::Pawn => value += count as f64 * blend(get_pawn_value),
             _ => {}
         }
     }
 
     value
 }
+
+// A queen with open lines, especially towards the enemy king, is worth a bit more
+// than a boxed-in one. Blockers stop the lines, own pieces aren't reachable.
+fn queen_scope_adjustment(board: &Board, square: Square, color: Color) -> f64 {
+    let occupied = *board.combined();
+    let reach = (get_bishop_moves(square, occupied) | get_rook_moves(square, occupied))
+        & !board.color_combined(color);
+    let king_zone = get_king_moves(board.king_square(!color));
+
+    let scope = (reach.popcnt() as i32 - QUEEN_AVERAGE_SCOPE) as f64 * QUEEN_SCOPE_STEP;
+    let king_pressure = (reach & king_zone).popcnt() as f64 * QUEEN_KING_ZONE_BONUS;
+    (scope + king_pressure).clamp(-QUEEN_SCOPE_LIMIT, QUEEN_SCOPE_LIMIT)
+}
+
+// Adjustment for well known non-additive trades, from color's point of view
+fn evaluate_imbalance(board: &Board, color: Color) -> f64 {
+    let count = |piece: Piece, side: Color| -> i32 {
+        (board.pieces(piece) & board.color_combined(side)).popcnt() as i32
+    };
+    let minors = |side: Color| count(KNIGHT, side) + count(BISHOP, side);
+
+    let queen_diff = count(QUEEN, color) - count(QUEEN, !color);
+    let rook_diff = count(ROOK, color) - count(ROOK, !color);
+    let minor_diff = minors(color) - minors(!color);
+
+    let mut value = 0.0;
+
+    // Two rooks against a queen
+    if rook_diff >= 2 && queen_diff <= -1 {
+        value += IMBALANCE_ROOKS_VS_QUEEN;
+    }
+
+    // Three minor pieces against a queen
+    if minor_diff >= 3 && queen_diff <= -1 {
+        value += IMBALANCE_MINORS_VS_QUEEN;
+    }
+
+    // Two minor pieces against a rook (usually plus a pawn)
+    if minor_diff >= 2 && rook_diff <= -1 && queen_diff == 0 {
+        value += IMBALANCE_MINORS_VS_ROOK;
+    }
+
+    // The exchange down, with positional compensation for it
+    if minor_diff == 1 && rook_diff == -1 && queen_diff == 0 {
+        let own_knights = board.pieces(KNIGHT) & board.color_combined(color);
+        if own_knights
+            .into_iter()
+            .any(|sq| is_outpost(board, sq, color))
+        {
+            value += EXCHANGE_OUTPOST_COMPENSATION;
+        }
+        if count(BISHOP, color) >= 2 {
+            value += EXCHANGE_BISHOP_PAIR_COMPENSATION;
+        }
+        if count(PAWN, color) > count(PAWN, !color) {
+            value += EXCHANGE_PAWN_COMPENSATION;
+        }
+    }
+
+    value
+}
+
+// A square in the enemy half that an own pawn protects and no enemy pawn can attack
+fn is_outpost(board: &Board, square: Square, color: Color) -> bool {
+    let relative_rank = match color {
+        Color::White => square.get_rank().to_index(),
+        Color::Black => 7 - square.get_rank().to_index(),
+    };
+    if !(3..=5).contains(&relative_rank) {
+        return false;
+    }
+
+    let own_pawns = board.pieces(PAWN) & board.color_combined(color);
+    let enemy_pawns = board.pieces(PAWN) & board.color_combined(!color);
+    let protected = (get_pawn_attacks(square, !color, own_pawns)).0 != 0;
+    let attackers =
+        passed_pawn_mask(square.to_index(), color) & get_adjacent_files(square.get_file());
+
+    protected && (attackers & enemy_pawns).0 == 0
+}
+
+// Bonus for each castling right color still has. Read from the board every time,
+// so rights lost by a king or rook move count even if the piece went back home.
+fn evaluate_castling_rights(board: &Board, color: Color) -> f64 {
+    let rights = board.castle_rights(color);
+    let mut value = 0.0;
+    if rights.has_kingside() {
+        value += KINGSIDE_CASTLING_BONUS;
+    }
+    if rights.has_queenside() {
+        value += QUEENSIDE_CASTLING_BONUS;
+    }
+    value
+}
+
+// Pieces of color attacking square, with blockers taken into account
+fn attackers_of(board: &Board, square: Square, color: Color) -> BitBoard {
+    let occupied = *board.combined();
+    let queens = board.pieces(QUEEN);
+    let attackers = get_pawn_attacks(square, !color, *board.pieces(PAWN))
+        | (get_knight_moves(square) & board.pieces(KNIGHT))
+        | (get_bishop_moves(square, occupied) & (board.pieces(BISHOP) | queens))
+        | (get_rook_moves(square, occupied) & (board.pieces(ROOK) | queens))
+        | (get_king_moves(square) & board.pieces(KING));
+    attackers & board.color_combined(color)
+}
+
+// Penalize pawns pushed past the middle that no own pawn can defend any more
+// and that the enemy already attacks, so pawns aren't pushed just to gain space.
+// There's no pawn storm bonus yet, this is the counterweight it will need.
+fn evaluate_overextension(board: &Board, color: Color) -> f64 {
+    let own_pawns = board.pieces(PAWN) & board.color_combined(color);
+    let mut value = 0.0;
+
+    for square in own_pawns {
+        let relative_rank = match color {
+            Color::White => square.get_rank().to_index(),
+            Color::Black => 7 - square.get_rank().to_index(),
+        };
+        if relative_rank < 4 {
+            continue;
+        }
+
+        // Own pawns on the adjacent files level with it or behind could still defend it
+        let adjacent = get_adjacent_files(square.get_file());
+        let ahead = passed_pawn_mask(square.to_index(), color);
+        let defendable = (own_pawns & adjacent & !ahead).0 != 0;
+
+        if !defendable && attackers_of(board, square, !color).0 != 0 {
+            value -= OVEREXTENDED_PAWN_PENALTY;
+        }
+    }
+
+    value
+}
+
+// Reward color's rooks lifted for an attack and penalize passive ones
+fn evaluate_rook_activity(board: &Board, color: Color) -> f64 {
+    let own_pieces = board.color_combined(color);
+    let enemy_king_file = board.king_square(!color).get_file().to_index() as i32;
+    let mut value = 0.0;
+
+    for square in board.pieces(ROOK) & own_pieces {
+        let relative_rank = match color {
+            Color::White => square.get_rank().to_index(),
+            Color::Black => 7 - square.get_rank().to_index(),
+        };
+        let mobility = (get_rook_moves(square, *board.combined()) & !own_pieces).popcnt();
+
+        if (2..=3).contains(&relative_rank) && mobility >= ROOK_LIFT_MIN_MOBILITY {
+            let file_distance = (square.get_file().to_index() as i32 - enemy_king_file).abs();
+            let closeness = (7 - file_distance) as f64 / 7.0;
+            value += ROOK_LIFT_BONUS * (0.5 + 0.5 * closeness);
+        } else if relative_rank == 0 && mobility <= PASSIVE_ROOK_MAX_MOBILITY {
+            value -= PASSIVE_ROOK_PENALTY;
+        }
+    }
+
+    value
+}
+
+// Bonus for color's knights and bishops that left home and for the pawns
+// holding or contesting the center
+fn evaluate_development(board: &Board, color: Color) -> f64 {
+    let own_pieces = board.color_combined(color);
+    let back_rank = get_rank(color.to_my_backrank());
+    let knight_homes = (get_file(File::B) | get_file(File::G)) & back_rank;
+    let bishop_homes = (get_file(File::C) | get_file(File::F)) & back_rank;
+    let center =
+        (get_file(File::D) | get_file(File::E)) & (get_rank(Rank::Fourth) | get_rank(Rank::Fifth));
+
+    let developed = (board.pieces(KNIGHT) & own_pieces & !knight_homes).popcnt()
+        + (board.pieces(BISHOP) & own_pieces & !bishop_homes).popcnt();
+    let own_pawns = board.pieces(PAWN) & own_pieces;
+    let center_control = (center & (own_pawns | pawn_attacks(board, color))).popcnt();
+
+    developed as f64 * DEVELOPED_MINOR_BONUS + center_control as f64 * CENTER_CONTROL_BONUS
+}
+
+// Penalize color's knights and bishops on the edge, where they reach fewer squares
+fn evaluate_rim_pieces(board: &Board, color: Color, phase: &GamePhase) -> f64 {
+    let own_pieces = board.color_combined(color);
+    let side_files = FILE_A | FILE_H;
+    let edge = side_files | get_rank(Rank::First) | get_rank(Rank::Eighth);
+    let corners = side_files & (get_rank(Rank::First) | get_rank(Rank::Eighth));
+
+    let knights = board.pieces(KNIGHT) & own_pieces;
+    let bishops = board.pieces(BISHOP) & own_pieces;
+    let penalty = (knights & edge).popcnt() as f64 * KNIGHT_RIM_PENALTY
+        + (knights & corners).popcnt() as f64 * KNIGHT_CORNER_PENALTY
+        + (bishops & side_files).popcnt() as f64 * BISHOP_RIM_PENALTY;
+
+    match phase {
+        GamePhase::Endgame => -penalty / 2.0,
+        _ => -penalty,
+    }
+}
+
+// Squares attacked by color's pawns
+fn pawn_attacks(board: &Board, color: Color) -> BitBoard {
+    let pawns = board.pieces(PAWN) & board.color_combined(color);
+    pawns.into_iter().fold(BitBoard(0), |attacks, sq| {
+        attacks | get_pawn_attacks(sq, color, BitBoard(!0))
+    })
+}
+
+// Penalize bishops and knights stranded on the rim of the enemy camp, like a
+// bishop that took on h7 and got shut in by ...g6, when every square they could
+// move to (blockers considered) is taken by an own piece or covered by a pawn
+// or the enemy king
+fn evaluate_trapped_pieces(board: &Board, color: Color) -> f64 {
+    let own_pieces = board.color_combined(color);
+    let enemy_king = board.king_square(!color);
+    let unsafe_squares = own_pieces
+        | pawn_attacks(board, !color)
+        | BitBoard::from_square(enemy_king)
+        | get_king_moves(enemy_king);
+    let rim_files = FILE_A | FILE_B | FILE_G | FILE_H;
+    let mut value = 0.0;
+
+    for piece in [BISHOP, KNIGHT] {
+        for square in board.pieces(piece) & own_pieces {
+            let relative_rank = match color {
+                Color::White => square.get_rank().to_index(),
+                Color::Black => 7 - square.get_rank().to_index(),
+            };
+            let on_rim = (rim_files & BitBoard::from_square(square)).0 != 0;
+            let (exposed, moves, piece_value) = match piece {
+                Piece::Bishop => (
+                    on_rim && relative_rank >= 5,
+                    get_bishop_moves(square, *board.combined()),
+                    BISHOP_VALUE,
+                ),
+                _ => (
+                    (on_rim && relative_rank >= 5) || relative_rank == 7,
+                    get_knight_moves(square),
+                    KNIGHT_VALUE_MIDDLEGAME,
+                ),
+            };
+
+            if exposed && (moves & !unsafe_squares).0 == 0 {
+                value -= piece_value * TRAPPED_PIECE_PENALTY;
+            }
+        }
+    }
+
+    value
+}
+
+// Squares in front of a pawn, on its own and the adjacent files
+fn passed_pawn_mask(square: usize, color: Color) -> BitBoard {
+    let file = square % 8;
+    let rank = square / 8;
+    let mut files = FILE_A.0 << file;
+    if file > 0 {
+        files |= FILE_A.0 << (file - 1);
+    }
+    if file < 7 {
+        files |= FILE_A.0 << (file + 1);
+    }
+
+    let ahead = match color {
+        Color::White => (!0u64).checked_shl(((rank + 1) * 8) as u32).unwrap_or(0),
+        Color::Black => (1u64 << (rank * 8)) - 1,
+    };
+    BitBoard(files & ahead)
+}
+
+// A pawn with no enemy pawns in front of it or on the adjacent files
+fn is_passed_pawn(board: &Board, square: usize, color: Color) -> bool {
+    let enemy_pawns = board.pieces(PAWN) & board.color_combined(!color);
+    (passed_pawn_mask(square, color) & enemy_pawns).0 == 0
+}
+
+// A pawn no friendly pawn can come up to protect any more, whose stop square
+// is covered by an enemy pawn
+fn is_backward_pawn(board: &Board, square: Square, color: Color) -> bool {
+    let own_pawns = board.pieces(PAWN) & board.color_combined(color);
+    let enemy_pawns = board.pieces(PAWN) & board.color_combined(!color);
+    let stop_sq = match square.forward(color) {
+        Some(sq) => sq,
+        None => return false,
+    };
+
+    // Pawns on the adjacent files level with it or behind can still support it
+    let adjacent = get_adjacent_files(square.get_file());
+    let ahead = passed_pawn_mask(square.to_index(), color);
+    if (own_pawns & adjacent & !ahead).0 != 0 {
+        return false;
+    }
+
+    get_pawn_attacks(stop_sq, color, enemy_pawns).0 != 0
+}
+
+// Penalize color's backward pawns, more so when an enemy rook sits on their
+// semi-open file. The rooks' side of it is in get_rook_position_bonus.
+fn evaluate_backward_pawns(board: &Board, color: Color) -> f64 {
+    let mut value = 0.0;
+    let pawns = board.pieces(PAWN) & board.color_combined(color);
+    let opponent_pawns = board.pieces(PAWN) & board.color_combined(!color);
+    let opponent_rooks = board.pieces(ROOK) & board.color_combined(!color);
+
+    for square in pawns {
+        if !is_backward_pawn(board, square, color) {
+            continue;
+        }
+
+        let file = get_file(square.get_file());
+        value -= tuning::BACKWARD_PAWN_PENALTY.pawns();
+        if (file & opponent_pawns).0 == 0 && (file & opponent_rooks).0 != 0 {
+            value -= BACKWARD_PAWN_ROOK_PRESSURE_PENALTY;
+        }
+    }
+
+    value
+}
+
+// Bonus for each of color's passed pawns that has another passed pawn next to it,
+// level with it or one rank apart, so the pair can advance supporting each other
+fn evaluate_connected_passers(board: &Board, color: Color) -> f64 {
+    let own_pawns = board.pieces(PAWN) & board.color_combined(color);
+    let passers: Vec<Square> = own_pawns
+        .into_iter()
+        .filter(|sq| is_passed_pawn(board, sq.to_index(), color))
+        .collect();
+
+    let mut value = 0.0;
+    for &square in &passers {
+        let connected = passers.iter().any(|other| {
+            let file_gap =
+                (other.get_file().to_index() as i32 - square.get_file().to_index() as i32).abs();
+            let rank_gap =
+                (other.get_rank().to_index() as i32 - square.get_rank().to_index() as i32).abs();
+            file_gap == 1 && rank_gap <= 1
+        });
+        if connected {
+            let relative_rank = match color {
+                Color::White => square.get_rank().to_index(),
+                Color::Black => 7 - square.get_rank().to_index(),
+            };
+            value += CONNECTED_PASSER_BONUS[relative_rank];
+        }
+    }
+
+    value
+}
+
+// Reward knights blockading the enemy's passed pawns and penalize more valuable
+// pieces that are tied down to the job, from color's point of view
+fn evaluate_blockades(board: &Board, color: Color) -> f64 {
+    let mut value = 0.0;
+    let enemy_pawns = board.pieces(PAWN) & board.color_combined(!color);
+    let own_pieces = board.color_combined(color);
+
+    for pawn_sq in enemy_pawns {
+        if !is_passed_pawn(board, pawn_sq.to_index(), !color) {
+            continue;
+        }
+
+        // The square directly in front of the passer
+        let blockade_sq = match pawn_sq.forward(!color) {
+            Some(sq) => sq,
+            None => continue,
+        };
+        if (own_pieces & BitBoard::from_square(blockade_sq)).0 == 0 {
+            continue;
+        }
+
+        value += match board.piece_on(blockade_sq) {
+            Some(Piece::Knight) => tuning::KNIGHT_BLOCKADE_BONUS.pawns(),
+            Some(Piece::Bishop) => -BISHOP_BLOCKADE_PENALTY,
+            Some(Piece::Queen) => -QUEEN_BLOCKADE_PENALTY,
+            _ => 0.0,
+        };
+    }
+
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // For tests that switch the global options, which the other tests read
+    static GLOBALS: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    // A test that panicked while holding the lock says nothing about the others
+    fn lock_globals() -> std::sync::MutexGuard<'static, ()> {
+        GLOBALS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::from_str(fen).unwrap()
+    }
+
+    // Piece values and imbalance of one side, as evaluate_terms weighs them
+    fn material(board: &Board, color: Color) -> f64 {
+        evaluate_material(board, color, &GamePhase::Middlegame, material_phase(board))
+            + evaluate_imbalance(board, color)
+    }
+
+    #[test]
+    fn two_rooks_against_a_queen_is_about_even() {
+        let board = board("3rr1k1/pppppppp/8/8/3Q4/8/PPPPPPPP/6K1 w - - 0 1");
+        let raw = evaluate_material(&board, Color::Black, &GamePhase::Middlegame, 0)
+            - evaluate_material(&board, Color::White, &GamePhase::Middlegame, 0);
+        let balance = material(&board, Color::Black) - material(&board, Color::White);
+        assert!(raw > 1.0, "raw rook lead {}", raw);
+        assert!(
+            balance.abs() < 0.75,
+            "rooks against queen scored {}",
+            balance
+        );
+    }
+
+    #[test]
+    fn knight_is_the_better_blockader() {
+        let knight = board("4k3/8/8/3p4/3N4/8/8/4K3 w - - 0 1");
+        let queen = board("4k3/8/8/3p4/3Q4/8/8/4K3 w - - 0 1");
+        assert!(evaluate_blockades(&knight, Color::White) > 0.0);
+        assert!(evaluate_blockades(&queen, Color::White) < 0.0);
+    }
+
+    #[test]
+    fn every_draw_is_scored_with_contempt() {
+        let mut repetition = Position::from_fen(FEN_START).unwrap();
+        for mv in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            assert!(repetition.make_move(mv));
+        }
+        let draws = [
+            (
+                "stalemate",
+                Position::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap(),
+            ),
+            (
+                "fifty moves",
+                Position::from_fen("7k/8/8/8/8/8/R7/K7 w - - 100 80").unwrap(),
+            ),
+            (
+                "insufficient material",
+                Position::from_fen("7k/8/8/8/8/8/8/KN6 w - - 0 1").unwrap(),
+            ),
+            ("repetition", repetition),
+        ];
+
+        let contempt = Score(30);
+        for (kind, position) in draws {
+            for ply in [1, 2] {
+                let mut params = SearchParams {
+                    depth: 2,
+                    contempt,
+                    ..SearchParams::default()
+                };
+                let (score, _) = alpha_beta_search(
+                    &position,
+                    2,
+                    ply,
+                    -Score::INFINITY,
+                    Score::INFINITY,
+                    &mut params,
+                );
+                assert_eq!(score, draw_score(contempt, ply), "{} at ply {}", kind, ply);
+            }
+        }
+        assert_eq!(draw_score(contempt, 1), -draw_score(contempt, 2));
+    }
+
+    #[test]
+    fn fast_eval_is_material_and_piece_squares_only() {
+        let _lock = lock_globals();
+        // The queens' scope and the rook's open file add to the full material,
+        // and a pawnless rook against a bishop is scaled down as drawish
+        for fen in [
+            "3qk3/4p3/8/8/8/8/4P3/R2QK3 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/R3K3 w - - 0 1",
+        ] {
+            let board = board(fen);
+            let phase = material_phase(&board);
+            let pst = PstScore::from_board(&board);
+            let side = |color| pst.value(color, phase) + pst.material(color, phase);
+            let expected = Score::from_pawns(side(Color::White) - side(Color::Black));
+
+            set_fast_eval(true);
+            let fast = evaluate_board(&board, 60);
+            set_fast_eval(false);
+            assert_eq!(fast, expected, "{}", fen);
+            assert_ne!(evaluate_board(&board, 60), expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn search_stops_at_the_ply_cap() {
+        // Checks on every move, and an iteration depth that allows the check
+        // extensions all the way, so only the cap can end the line
+        let position = Position::from_fen("6k1/5pp1/8/8/7Q/8/qq4PP/7K w - - 0 1").unwrap();
+        let mut params = SearchParams {
+            depth: MAX_PLY,
+            ..SearchParams::default()
+        };
+        let (score, _) = alpha_beta_search(
+            &position,
+            8,
+            MAX_PLY - 4,
+            -Score::INFINITY,
+            Score::INFINITY,
+            &mut params,
+        );
+        assert_eq!(params.seldepth, MAX_PLY);
+        assert!(score.0.abs() < Score::INFINITY.0);
+    }
+
+    #[test]
+    fn exchange_down_with_compensation_is_closer_to_even() {
+        // A knight on the d5 outpost and a pawn more against the rook
+        let board = board("r3k3/pp3ppp/3p4/3N4/4P3/8/PPP2PPP/4K3 w - - 0 1");
+        let phase = material_phase(&board);
+        let raw = evaluate_material(&board, Color::White, &GamePhase::Middlegame, phase)
+            - evaluate_material(&board, Color::Black, &GamePhase::Middlegame, phase);
+        let balance = material(&board, Color::White) - material(&board, Color::Black);
+        assert!(raw < 0.0, "raw {}", raw);
+        assert!(
+            (balance - raw - EXCHANGE_OUTPOST_COMPENSATION - EXCHANGE_PAWN_COMPENSATION).abs()
+                < 1e-9,
+            "raw {} balance {}",
+            raw,
+            balance
+        );
+    }
+
+    #[test]
+    fn king_walk_home_keeps_the_castling_rights_lost() {
+        let play = |moves: &[&str]| {
+            let mut position = Position::from_fen(FEN_START).unwrap();
+            for mv in moves {
+                assert!(position.make_move(mv));
+            }
+            position.board
+        };
+        // The same placement, once with the kings out and back, once with the knights
+        let kings = play(&["e2e4", "e7e5", "e1e2", "e8e7", "e2e1", "e7e8"]);
+        let knights = play(&["e2e4", "e7e5", "g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert_eq!(kings.combined(), knights.combined());
+        for color in [Color::White, Color::Black] {
+            assert_eq!(evaluate_castling_rights(&kings, color), 0.0);
+            assert!(evaluate_castling_rights(&knights, color) > 0.0);
+        }
+    }
+
+    #[test]
+    fn root_ties_go_to_the_pv_move_then_forcing_then_central_moves() {
+        let position = Position::from_fen("4k3/8/8/3p4/8/2N5/8/4K3 w - - 0 1").unwrap();
+        let mut params = SearchParams::default();
+        let key = |mv: &str, params: &SearchParams| root_tie_break(&position, mv, params);
+
+        assert!(key("c3d5", &params) > key("c3e4", &params));
+        assert!(key("c3e4", &params) > key("c3a4", &params));
+        params.pv_move = Some("c3a4".to_string());
+        assert!(key("c3a4", &params) > key("c3d5", &params));
+        // The same move always gets the same key, so ties break the same way every run
+        assert_eq!(key("c3b5", &params), key("c3b5", &params));
+    }
+
+    #[test]
+    fn backward_pawn_under_rook_pressure_is_worst() {
+        // d6 can't be supported and c4 guards d5, the rook bears down the d-file
+        let pressed = board("3k4/8/3p4/4p3/2P5/8/8/3RK3 w - - 0 1");
+        let unpressed = board("3k4/8/3p4/4p3/2P5/8/8/4K2R w - - 0 1");
+        // c7 can still come to d6's support
+        let defended = board("3k4/2p5/3p4/4p3/2P5/8/8/3RK3 w - - 0 1");
+
+        let pressed = evaluate_backward_pawns(&pressed, Color::Black);
+        let unpressed = evaluate_backward_pawns(&unpressed, Color::Black);
+        assert!(
+            pressed < unpressed && unpressed < 0.0,
+            "{} {}",
+            pressed,
+            unpressed
+        );
+        assert_eq!(evaluate_backward_pawns(&defended, Color::Black), 0.0);
+    }
+
+    // Plain minimax to a fixed depth with a full-window quiescence at the
+    // leaves, nothing pruned or reduced
+    fn unpruned_search(position: &Position, depth: i32, ply: i32) -> (Score, Vec<String>) {
+        let moves = position.generate_legal_moves();
+        if moves.is_empty() {
+            return (adjudicate(&position.board), Vec::new());
+        }
+        if depth == 0 {
+            let mut params = SearchParams::default();
+            let score = quiescence(
+                position,
+                -Score::INFINITY,
+                Score::INFINITY,
+                ply,
+                0,
+                &mut params,
+            );
+            return (score, Vec::new());
+        }
+
+        let mut best = -Score::INFINITY;
+        let mut best_moves = Vec::new();
+        for mv in moves {
+            let mut child = position.clone();
+            child.make_move(&mv);
+            let score = -unpruned_search(&child, depth - 1, ply + 1).0;
+            if score > best {
+                best = score;
+                best_moves.clear();
+            }
+            if score == best {
+                best_moves.push(mv);
+            }
+        }
+        (best, best_moves)
+    }
+
+    #[test]
+    fn analyse_mode_matches_an_unpruned_search() {
+        let _lock = lock_globals();
+        // Kings far from the pawns and no pawn near promotion, so no checks and
+        // no check extensions that the plain search wouldn't make
+        let position = Position::from_fen("7k/8/8/3p1p2/2P1P3/8/8/K7 w - - 0 1").unwrap();
+        let depth = 4;
+        let (expected, best_moves) = unpruned_search(&position, depth, 0);
+
+        set_analyse_mode(true);
+        let mut params = SearchParams {
+            depth,
+            ..SearchParams::default()
+        };
+        let (score, best_move) = alpha_beta_search(
+            &position,
+            depth,
+            0,
+            -Score::INFINITY,
+            Score::INFINITY,
+            &mut params,
+        );
+        set_analyse_mode(false);
+
+        assert_eq!(score, expected);
+        assert!(best_moves.contains(&best_move.unwrap()), "{:?}", best_moves);
+    }
+
+    #[test]
+    fn bishop_trapped_on_h7_is_penalized() {
+        // g8 is next to the king and g6 is guarded by f7
+        let trapped = board("5k2/5p1B/6p1/8/8/8/8/4K3 w - - 0 1");
+        // Without the g6 pawn the diagonal is open
+        let free = board("5k2/5p1B/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            evaluate_trapped_pieces(&trapped, Color::White),
+            -BISHOP_VALUE * TRAPPED_PIECE_PENALTY
+        );
+        assert_eq!(evaluate_trapped_pieces(&free, Color::White), 0.0);
+    }
+
+    #[test]
+    fn overextended_pawn_is_penalized() {
+        // g5 is attacked by the knight and no pawn can come to its side
+        let alone = board("4k3/8/4n3/6P1/8/8/8/4K3 w - - 0 1");
+        // h4 can still defend it
+        let backed = board("4k3/8/4n3/6P1/7P/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            evaluate_overextension(&alone, Color::White),
+            -OVEREXTENDED_PAWN_PENALTY
+        );
+        assert_eq!(evaluate_overextension(&backed, Color::White), 0.0);
+    }
+
+    #[test]
+    fn black_to_move_scores_for_black_and_checks_are_not_stood_on() {
+        // The eval weights must not change between the searches compared below
+        let _lock = lock_globals();
+        // Scores come back for the side to move (negamax), so a Black mate is
+        // positive for Black and a mated Black gets the mated score
+        let mating = Position::from_fen("8/8/8/8/8/6k1/5q2/7K b - - 0 1").unwrap();
+        let mut params = SearchParams {
+            depth: 2,
+            ..SearchParams::default()
+        };
+        let (score, best) = alpha_beta_search(
+            &mating,
+            2,
+            0,
+            -Score::INFINITY,
+            Score::INFINITY,
+            &mut params,
+        );
+        assert_eq!(score, Score::mate_in(1));
+        assert_eq!(best.as_deref(), Some("f2g2"));
+
+        let mated = Position::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        let mut params = SearchParams::default();
+        let score = quiescence(&mated, -Score::INFINITY, Score::INFINITY, 3, 0, &mut params);
+        assert_eq!(score, Score::mated_in(3));
+
+        // Black is in check and has to move the king. A window the static eval
+        // already fails high on would end a stand-pat search at once, the
+        // evasions have to be searched instead.
+        let forked = Position::from_fen("2q1k3/8/3N4/8/8/8/8/4K3 b - - 0 1").unwrap();
+        let stand_pat = evaluate_position(&forked);
+        let mut params = SearchParams::default();
+        let expected = MoveGen::new_legal(&forked.board)
+            .map(|mv| {
+                let mut child = forked.clone();
+                child.make_move(&mv.to_string());
+                -quiescence(&child, -Score::INFINITY, Score::INFINITY, 1, 1, &mut params)
+            })
+            .max()
+            .unwrap();
+        let mut params = SearchParams::default();
+        let score = quiescence(
+            &forked,
+            -Score::INFINITY,
+            Score::INFINITY,
+            0,
+            0,
+            &mut params,
+        );
+        assert_eq!(score, expected);
+        let score = quiescence(&forked, -Score::INFINITY, stand_pat, 0, 0, &mut params);
+        assert_ne!(score, stand_pat);
+    }
+
+    #[test]
+    fn connected_passers_on_the_sixth_are_worth_far_more_than_separated_ones() {
+        let connected = board("6k1/8/3PP3/8/8/8/8/K7 w - - 0 1");
+        let separated = board("6k1/8/1P4P1/8/8/8/8/K7 w - - 0 1");
+        let bonus = evaluate_connected_passers(&connected, Color::White);
+        assert_eq!(evaluate_connected_passers(&separated, Color::White), 0.0);
+        assert!(bonus > 1.5, "connected passers got {}", bonus);
+
+        // The same from Black's side of the board
+        let black = board("k7/8/8/8/8/3pp3/8/6K1 b - - 0 1");
+        assert_eq!(evaluate_connected_passers(&black, Color::Black), bonus);
+    }
+
+    #[test]
+    fn winning_root_prefers_progress_without_changing_the_score() {
+        // a7 and a rook lift both mate in two. The lift is the previous best move,
+        // which would win the tie, but the pawn push makes progress.
+        let position = Position::from_fen("7k/8/P5K1/8/8/8/2P5/2R5 w - - 0 1").unwrap();
+        let mut params = SearchParams {
+            depth: 3,
+            pv_move: Some("c1d1".to_string()),
+            ..SearchParams::default()
+        };
+        let (score, best) = alpha_beta_search(
+            &position,
+            3,
+            0,
+            -Score::INFINITY,
+            Score::INFINITY,
+            &mut params,
+        );
+        assert_eq!(best.as_deref(), Some("a6a7"));
+        assert_eq!(score, Score::mate_in(3));
+    }
+
+    #[test]
+    fn aggressive_style_weighs_the_king_attack_more() {
+        let _lock = lock_globals();
+        // Queen and bishop both aim at f7
+        let board = board("r1bqkb1r/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4");
+        let pst = PstScore::from_board(&board);
+        let king_attack = |style| {
+            assert!(tuning::apply_style(style));
+            let weight = tuning::KING_ATTACK_WEIGHT.get();
+            (weight, evaluate_terms(&board, 7, &pst).king_attack[0])
+        };
+
+        let (balanced_weight, balanced) = king_attack("Balanced");
+        let (aggressive_weight, aggressive) = king_attack("Aggressive");
+        assert!(tuning::apply_style("Balanced"));
+        assert_eq!(balanced_weight, tuning::KING_ATTACK_WEIGHT.default);
+        assert!(aggressive_weight > balanced_weight);
+        assert!(balanced > 0.0, "no king attack found");
+        assert!(aggressive > balanced, "{} against {}", aggressive, balanced);
+    }
+
+    #[test]
+    fn open_queen_near_an_exposed_king_is_worth_more_than_a_boxed_in_one() {
+        let open = board("7k/8/8/3Q4/8/8/PP6/K7 w - - 0 1");
+        let boxed = board("6k1/8/8/8/8/8/PPN5/QBK5 w - - 0 1");
+        let open_value = queen_scope_adjustment(&open, Square::D5, Color::White);
+        let boxed_value = queen_scope_adjustment(&boxed, Square::A1, Color::White);
+        assert!(open_value > 0.0, "open queen {}", open_value);
+        assert!(boxed_value < 0.0, "boxed-in queen {}", boxed_value);
+        assert!(open_value.abs() <= QUEEN_SCOPE_LIMIT && boxed_value.abs() <= QUEEN_SCOPE_LIMIT);
+    }
+
+    #[test]
+    fn open_semi_open_and_closed_files_score_apart() {
+        // a-file open, d-file half-open onto the backward d6, e-file half-open onto
+        // the supported e5, c-file closed by the own pawn
+        let board = board("3k4/8/3p4/4p3/2P5/8/8/R1RRR2K w - - 0 1");
+        let bonus = |square: Square| {
+            get_rook_position_bonus(&analyze_rook_position(
+                &board,
+                square.to_index(),
+                Color::White,
+            ))
+        };
+        let (open, backward, semi_open, closed) = (
+            bonus(Square::A1),
+            bonus(Square::D1),
+            bonus(Square::E1),
+            bonus(Square::C1),
+        );
+        assert!(
+            analyze_rook_position(&board, Square::D1.to_index(), Color::White)
+                .targets_backward_pawn
+        );
+        assert_eq!(closed, 0.0);
+        assert!(
+            semi_open > closed && semi_open < open,
+            "{} {}",
+            semi_open,
+            open
+        );
+        assert!(backward > semi_open, "{} {}", backward, semi_open);
+        assert_ne!(backward, open);
+    }
+
+    #[test]
+    fn knight_in_the_corner_is_worse_than_in_the_center() {
+        let corner = board("N3k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        let center = board("4k3/8/8/3N4/8/8/8/4K3 w - - 0 1");
+        for phase in [GamePhase::Middlegame, GamePhase::Endgame] {
+            let rim = evaluate_rim_pieces(&corner, Color::White, &phase);
+            assert!(rim < 0.0, "rim {}", rim);
+            assert_eq!(evaluate_rim_pieces(&center, Color::White, &phase), 0.0);
+        }
+        // The piece-square tables already agree, the rim term adds to them
+        let placement = |board: &Board| {
+            PstScore::from_board(board).value(Color::White, material_phase(board))
+                + evaluate_rim_pieces(board, Color::White, &GamePhase::Middlegame)
+        };
+        assert!(placement(&corner) < placement(&center));
+    }
+
+    #[test]
+    fn knight_forking_rook_and_queen_earns_the_rook_share() {
+        let fork = board("r3q2k/2N5/8/8/8/8/8/6K1 b - - 0 1");
+        let no_fork = board("r3q2k/8/8/8/8/8/3N4/6K1 b - - 0 1");
+        let phase = GamePhase::Middlegame;
+        let bonus = evaluate_forks(&fork, Color::White, &phase);
+        assert_eq!(
+            bonus,
+            get_piece_base_value(Piece::Rook, &phase) * FORK_BONUS_SHARE
+        );
+        assert!(bonus > 1.0, "fork bonus {}", bonus);
+        assert_eq!(evaluate_forks(&no_fork, Color::White, &phase), 0.0);
+    }
+
+    #[test]
+    fn rook_lifted_towards_the_king_beats_a_passive_one() {
+        let lifted = board("6k1/5ppp/8/8/8/6R1/5PPP/6K1 w - - 0 1");
+        let far_lift = board("6k1/5ppp/8/8/8/R7/5PPP/6K1 w - - 0 1");
+        let passive = board("6k1/5ppp/8/8/8/8/5PPP/4BRK1 w - - 0 1");
+        let lifted = evaluate_rook_activity(&lifted, Color::White);
+        let far_lift = evaluate_rook_activity(&far_lift, Color::White);
+        let passive = evaluate_rook_activity(&passive, Color::White);
+        assert!(passive < 0.0, "passive rook {}", passive);
+        assert!(
+            lifted > far_lift && far_lift > 0.0,
+            "{} {}",
+            lifted,
+            far_lift
+        );
+    }
+
+    #[test]
+    fn evaluation_stays_finite_when_kings_join_exchanges() {
+        let limit = Score::from_pawns(tuning::EVAL_LIMIT.pawns());
+        for fen in [
+            // The king is the only one to take back, or to take at all
+            "4k3/8/8/3q4/4K3/8/8/8 w - - 0 1",
+            "4k3/8/8/8/3nK3/8/8/8 b - - 0 1",
+            "8/8/8/2k5/3P4/4K3/8/8 b - - 0 1",
+            // Kings and queens crowding the same squares
+            "8/8/2k5/2q5/2Q5/2K5/8/8 w - - 0 1",
+            "r3k2r/ppp2ppp/2n1Bn2/3qp3/3QP3/2N1bN2/PPP2PPP/R3K2R w KQkq - 0 1",
+        ] {
+            let position = Position::from_fen(fen).unwrap();
+            // The position and every one a move away
+            let mut positions = vec![position.clone()];
+            for mv in position.generate_legal_moves() {
+                let mut child = position.clone();
+                child.make_move(&mv);
+                positions.push(child);
+            }
+            for position in positions {
+                let terms = evaluate_terms(&position.board, position.move_count, &position.pst);
+                for color in [Color::White, Color::Black] {
+                    assert!(terms.total(color).is_finite(), "{}", position.fen());
+                }
+                let score = evaluate_position(&position);
+                assert!(score.0.abs() <= limit.0, "{}", position.fen());
+                assert!(!score.is_mate(), "{}", position.fen());
+            }
+        }
+    }
+
+    #[test]
+    fn bishop_and_knight_on_f7_against_an_uncastled_king() {
+        let uncastled =
+            board("r1bqkb1r/pppp1ppp/2n2n2/4p1N1/2B1P3/8/PPPP1PPP/RNBQK2R b KQkq - 5 4");
+        let castled = board("r1bq1rk1/pppp1ppp/2n2n2/2b1p1N1/2B1P3/8/PPPP1PPP/RNBQK2R w KQ - 7 6");
+        // Two attackers against the king alone
+        assert_eq!(
+            evaluate_f7_attack(&uncastled, Color::White),
+            2.0 * F7_ATTACKER_BONUS + F7_WEAK_DEFENSE_BONUS
+        );
+        assert_eq!(evaluate_f7_attack(&castled, Color::White), 0.0);
+        // Black has nothing on f2
+        assert_eq!(evaluate_f7_attack(&uncastled, Color::Black), 0.0);
+    }
+
+    #[test]
+    fn locked_pawn_wall_with_kings_kept_out_is_a_draw() {
+        let locked = board("4k3/8/1p1p1p1p/pPpPpPpP/P1P1P1P1/8/8/4K3 w - - 0 1");
+        assert!(is_dead_position(&locked));
+        assert_eq!(evaluate_board(&locked, 80), Score::DRAW);
+
+        // Without the a- and b-pawns the white king walks round to d6
+        let open_file = board("4k3/8/3p1p1p/2pPpPpP/2P1P1P1/8/8/4K3 w - - 0 1");
+        assert!(!is_dead_position(&open_file));
+        // A pawn that can still take isn't locked either
+        let capture = board("4k3/8/1p1p1p1p/pPpPpPpP/P1P1P1P1/8/1p6/4K3 w - - 0 1");
+        assert!(!is_dead_position(&capture));
+    }
+
+    #[test]
+    fn typed_pick_is_the_parsed_string_pick() {
+        // Bare kings are a draw at every node, so the search runs through its
+        // depths at once, and the tie-breaks pick the same move each time
+        let mut position = Position::from_fen("8/8/8/4k3/8/8/8/4K3 w - - 0 1").unwrap();
+        let typed = pick_move_typed(&mut position).unwrap();
+        assert!(position.board.legal(typed));
+        let text = pick_move(&mut position).unwrap();
+        assert_eq!(Some(typed), text.parse().ok());
+    }
+
+    #[test]
+    fn queen_and_knight_attack_a_bit_more_than_queen_and_bishop() {
+        let knight = board("6k1/5ppp/8/6NQ/8/8/5PPP/6K1 w - - 0 1");
+        let bishop = board("6k1/5ppp/8/7Q/8/3B4/5PPP/6K1 w - - 0 1");
+        let partners = |board: &Board, phase| evaluate_queen_partners(board, Color::White, &phase);
+
+        let with_knight = partners(&knight, GamePhase::Middlegame);
+        let with_bishop = partners(&bishop, GamePhase::Middlegame);
+        assert!(with_bishop > 0.0, "queen and bishop {}", with_bishop);
+        assert!(with_knight > with_bishop, "{} {}", with_knight, with_bishop);
+        assert!(
+            with_knight - with_bishop < 0.25,
+            "{} {}",
+            with_knight,
+            with_bishop
+        );
+
+        // Less as the material comes off, nothing in the endgame
+        assert!(partners(&knight, GamePhase::Threshold) < with_knight);
+        assert_eq!(partners(&knight, GamePhase::Endgame), 0.0);
+    }
+
+    #[test]
+    fn start_position_gets_symmetric_piece_square_values() {
+        let start = board(FEN_START);
+        let pst = PstScore::from_board(&start);
+        // From the opening through to the endgame tables
+        for phase in [material_phase(&start), PHASE_MAX / 2, 0] {
+            let white = pst.value(Color::White, phase);
+            assert_ne!(white, 0.0, "phase {}", phase);
+            assert_eq!(white, pst.value(Color::Black, phase), "phase {}", phase);
+        }
+        // and the eval counts them in the opening
+        let [white, black] = evaluate_terms(&start, 0, &pst).piece_square;
+        assert!(white != 0.0 && white == black, "{} {}", white, black);
+    }
+
+    #[test]
+    fn deep_enough_entry_settles_the_node_at_once() {
+        let _lock = lock_globals();
+        let position = Position::from_fen("1k6/p7/1p6/8/5N2/8/6PP/3R2K1 w - - 0 1").unwrap();
+        let key = position.board.get_hash();
+        let stored = |flag, score| TtEntry {
+            key,
+            depth: 10,
+            score,
+            flag,
+            best_move: Some(ChessMove::from_str("d1d7").unwrap()),
+        };
+        let search = |params: &mut SearchParams| {
+            alpha_beta_search(&position, 4, 1, Score(-50), Score(50), params)
+        };
+        TT.new_search();
+
+        // A shallower entry doesn't settle anything, the node is searched
+        TT.store(TtEntry {
+            depth: 2,
+            ..stored(Bound::Exact, Score(17))
+        });
+        let mut params = SearchParams::default();
+        search(&mut params);
+        assert!(params.nodes > 1);
+
+        // An exact score comes back as it is, with its move, before any move is made
+        TT.store(stored(Bound::Exact, Score(17)));
+        let mut params = SearchParams::default();
+        let (score, best) = search(&mut params);
+        assert_eq!((score, best.as_deref()), (Score(17), Some("d1d7")));
+        assert_eq!((params.nodes, params.tt_hits), (1, 1));
+
+        // A lower bound at or above beta is a cutoff
+        TT.store(stored(Bound::Lower, Score(80)));
+        let mut params = SearchParams::default();
+        assert_eq!(search(&mut params).0, Score(80));
+        assert_eq!((params.nodes, params.beta_cutoffs), (1, 1));
+    }
+
+    #[test]
+    fn quiescence_sees_the_hanging_queen() {
+        let _lock = lock_globals();
+        // The black queen on d4 can be taken by the knight for nothing. Material
+        // only, so the queen is all the static eval sees.
+        let position = Position::from_fen("4k3/pp6/8/8/3q4/5N2/PP6/4K3 w - - 0 1").unwrap();
+        let mut taken = position.clone();
+        assert!(taken.make_move("f3d4"));
+        set_fast_eval(true);
+        let stand_pat = evaluate_position(&position);
+        let after_capture = -evaluate_position(&taken);
+        let mut params = SearchParams::default();
+        let score = quiescence(
+            &position,
+            -Score::INFINITY,
+            Score::INFINITY,
+            0,
+            0,
+            &mut params,
+        );
+        set_fast_eval(false);
+        assert!(stand_pat.centipawns() < -500, "{:?}", stand_pat);
+        assert!(after_capture.centipawns() > 200, "{:?}", after_capture);
+        assert_eq!(score, after_capture);
+        assert!(params.qnodes > 1);
+    }
+
+    #[test]
+    fn quiescence_ends_at_its_ply_cap() {
+        let _lock = lock_globals();
+        // Every piece stares at another, the capture chains go on and on
+        let position = Position::from_fen(
+            "r1bqk2r/pppp1ppp/2n2n2/1Bb1p1N1/3PP3/2N2Q2/PPP2PPP/R1B1K2R b KQkq - 0 1",
+        )
+        .unwrap();
+        let mut params = SearchParams {
+            qsearch_checks: true,
+            ..SearchParams::default()
+        };
+        let score = quiescence(
+            &position,
+            -Score::INFINITY,
+            Score::INFINITY,
+            0,
+            0,
+            &mut params,
+        );
+        assert!(score.0.abs() < Score::INFINITY.0);
+        assert!(params.seldepth <= QSEARCH_MAX_PLIES, "{}", params.seldepth);
+
+        // At the cap the static eval comes back without searching on
+        let mut params = SearchParams::default();
+        let capped = quiescence(
+            &position,
+            -Score::INFINITY,
+            Score::INFINITY,
+            1,
+            QSEARCH_MAX_PLIES,
+            &mut params,
+        );
+        assert_eq!(capped, evaluate_position(&position));
+        assert_eq!(params.qnodes, 1);
+    }
+
+    #[test]
+    fn pawnless_rook_against_bishop_is_scaled_towards_a_draw() {
+        let _lock = lock_globals();
+        let board = board("4kb2/8/8/8/8/8/8/R3K3 w - - 0 1");
+        let scale = drawish_endgame_scale(&board);
+        assert_eq!(scale, ROOK_VS_MINOR_PAWNLESS_SCALE);
+
+        // The rook's material lead is worth a couple of pawns unscaled
+        let terms = evaluate_terms(&board, 80, &PstScore::from_board(&board));
+        let lead = terms.material[0] - terms.material[1];
+        assert!(lead > 1.5, "rook lead {}", lead);
+        assert!(lead * scale < 0.5, "scaled rook lead {}", lead * scale);
+
+        // and the whole eval shrinks the same way
+        let unscaled = Score::from_pawns(terms.total(Color::White) - terms.total(Color::Black));
+        let scaled = evaluate_board(&board, 80);
+        let expected = (unscaled.0 as f64 * scale).round() as i32;
+        assert!(
+            (scaled.0 - expected).abs() <= 1,
+            "{:?} {:?}",
+            scaled,
+            unscaled
+        );
+    }
+
+    #[test]
+    fn developed_minor_pieces_are_a_development_lead() {
+        let board = board("rnbqkbnr/pppppppp/8/8/2B1P3/2N1BN2/PPPP1PPP/R2QK2R b KQkq - 0 1");
+        let white = evaluate_development(&board, Color::White);
+        let black = evaluate_development(&board, Color::Black);
+        assert!(
+            white - black >= 4.0 * DEVELOPED_MINOR_BONUS,
+            "{} against {}",
+            white,
+            black
+        );
+        assert_eq!(black, 0.0);
+    }
+
+    #[test]
+    fn killer_is_the_first_quiet_move_at_its_ply() {
+        let _lock = lock_globals();
+        let position =
+            Position::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2")
+                .unwrap();
+        let mut params = SearchParams::default();
+        params.store_killer(3, ChessMove::from_str("a2a3").unwrap());
+
+        let mut moves = position.generate_legal_moves();
+        order_moves(&mut moves, &position, &params, 3);
+        assert_eq!(moves[0], "a2a3");
+
+        // Other plies don't know about it
+        let mut moves = position.generate_legal_moves();
+        order_moves(&mut moves, &position, &params, 2);
+        assert_ne!(moves[0], "a2a3");
+    }
+
+    #[test]
+    fn history_reorders_quiets_as_cutoffs_accumulate() {
+        let _lock = lock_globals();
+        let position =
+            Position::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2")
+                .unwrap();
+        let h3 = ChessMove::from_str("h2h3").unwrap();
+        let first = |params: &SearchParams| {
+            let mut moves = position.generate_legal_moves();
+            order_moves(&mut moves, &position, params, 2);
+            moves[0].clone()
+        };
+        let mut params = SearchParams::default();
+        assert_ne!(first(&params), "h2h3");
+        params.store_history(10, h3);
+        params.store_history(10, h3);
+        assert_eq!(first(&params), "h2h3");
+
+        // Past the limit the whole table is halved
+        params.store_history(2, ChessMove::from_str("a2a3").unwrap());
+        params.store_history(10, h3);
+        params.store_history(10, h3);
+        assert_eq!(
+            params.history[h3.get_source().to_index()][h3.get_dest().to_index()],
+            200
+        );
+        assert_eq!(params.history[8][16], 2);
+    }
+
+    #[test]
+    fn null_window_scouts_find_the_same_move_with_fewer_nodes() {
+        let _lock = lock_globals();
+        let position = Position::from_fen(
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+        )
+        .unwrap();
+        let search = |scout_late_moves| {
+            // Each search starts from an empty table, or the second would live off the first
+            TT.clear();
+            TT.new_search();
+            // No deadline, a slow machine must not cut either search short
+            let mut params = SearchParams {
+                depth: 5,
+                max_time: Duration::MAX,
+                scout_late_moves,
+                ..SearchParams::default()
+            };
+            let (score, best) = alpha_beta_search(
+                &position,
+                5,
+                0,
+                -Score::INFINITY,
+                Score::INFINITY,
+                &mut params,
+            );
+            (score, best, params.nodes)
+        };
+        let (pvs_score, pvs_move, pvs_nodes) = search(true);
+        let (score, best, nodes) = search(false);
+        assert_eq!((pvs_score, pvs_move), (score, best));
+        assert!(pvs_nodes < nodes, "{} against {}", pvs_nodes, nodes);
+    }
+
+    #[test]
+    fn check_extensions_find_the_smothered_mate() {
+        let _lock = lock_globals();
+        // Nh6+ Kh8 Qg8+ Rxg8 Nf7#, all checks, five plies for a depth 3 search
+        let position = Position::from_fen("5rk1/5Npp/8/3Q4/8/8/8/6K1 w - - 0 1").unwrap();
+        let search = |iteration_depth| {
+            TT.clear();
+            TT.new_search();
+            // Extensions are capped at twice the iteration depth, so 0 turns them off
+            let mut params = SearchParams {
+                depth: iteration_depth,
+                ..SearchParams::default()
+            };
+            alpha_beta_search(
+                &position,
+                3,
+                0,
+                -Score::INFINITY,
+                Score::INFINITY,
+                &mut params,
+            )
+        };
+        let (score, best) = search(3);
+        assert_eq!(score, Score::mate_in(5));
+        assert_eq!(best.as_deref(), Some("f7h6"));
+        assert!(!search(0).0.is_mate());
+    }
+
+    #[test]
+    fn expiring_fifty_move_clock_scores_a_draw() {
+        let _lock = lock_globals();
+        // A rook up, but no move can take anything or push a pawn before the clock runs out
+        let search = |fen| {
+            let position = Position::from_fen(fen).unwrap();
+            let mut params = SearchParams {
+                depth: 2,
+                ..SearchParams::default()
+            };
+            alpha_beta_search(
+                &position,
+                2,
+                0,
+                -Score::INFINITY,
+                Score::INFINITY,
+                &mut params,
+            )
+            .0
+        };
+        assert_eq!(search("4k3/8/8/8/8/8/8/R3K3 w - - 99 80"), Score::DRAW);
+        assert_ne!(search("4k3/8/8/8/8/8/8/R3K3 w - - 0 80"), Score::DRAW);
+    }
+
+    // The same position with the colors swapped: ranks flipped, piece case
+    // swapped, the other side to move and castling rights traded
+    fn mirrored(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let swap_case = |text: &str| -> String {
+            text.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect()
+        };
+        let ranks: Vec<&str> = fields[0].split('/').rev().collect();
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let mut castling: Vec<char> = swap_case(fields[2]).chars().collect();
+        castling.sort_by_key(|c| (c.is_ascii_lowercase(), *c != 'K' && *c != 'k'));
+        let en_passant = match fields[3] {
+            "-" => "-".to_string(),
+            square => square.replace('3', "6").replace('6', "3"),
+        };
+        format!(
+            "{} {} {} {} {} {}",
+            swap_case(&ranks.join("/")),
+            side,
+            castling.into_iter().collect::<String>(),
+            en_passant,
+            fields[4],
+            fields[5]
+        )
+    }
+
+    #[test]
+    fn mirrored_positions_evaluate_exactly_the_same() {
+        let _lock = lock_globals();
+        for fen in [
+            FEN_START,
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            "r3k2r/1P6/8/3pP3/8/2n5/8/R3K2R w KQkq d6 0 1",
+            "8/5pk1/6p1/8/3B4/6P1/5PK1/8 b - - 0 40",
+            "4k3/8/8/3p4/3N4/8/8/4K3 w - - 0 1",
+        ] {
+            let mirror = mirrored(fen);
+            // Both scores are for the side to move, which the mirror swaps too
+            for move_count in [4, 60] {
+                assert_eq!(
+                    evaluate_board(&board(fen), move_count),
+                    evaluate_board(&board(&mirror), move_count),
+                    "{} against {}",
+                    fen,
+                    mirror
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mirrored_positions_search_to_the_same_score() {
+        let _lock = lock_globals();
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        let search = |fen: &str, depth| {
+            let position = Position::from_fen(fen).unwrap();
+            let mut params = SearchParams {
+                depth,
+                ..SearchParams::default()
+            };
+            alpha_beta_search(
+                &position,
+                depth,
+                0,
+                -Score::INFINITY,
+                Score::INFINITY,
+                &mut params,
+            )
+            .0
+        };
+        // Odd and even depths, each ending on a different side's eval
+        for depth in 1..=3 {
+            assert_eq!(
+                search(fen, depth),
+                search(&mirrored(fen), depth),
+                "depth {}",
+                depth
+            );
+        }
+    }
+
+    #[test]
+    fn queen_trade_moves_the_tapered_score_only_a_little() {
+        // White a knight up, the same position with and without the queens
+        let queens = board("r1bqkb1r/pppp1ppp/5n2/4p3/2B1P3/2N2N2/PPPP1PPP/R1BQK2R w KQkq - 0 1");
+        let traded = board("r1b1kb1r/pppp1ppp/5n2/4p3/2B1P3/2N2N2/PPPP1PPP/R1B1K2R w KQkq - 0 1");
+        let lead = |board: &Board, phase| {
+            let pst = PstScore::from_board(board);
+            let side = |color| pst.value(color, phase) + pst.material(color, phase);
+            side(Color::White) - side(Color::Black)
+        };
+        let (before, after) = (material_phase(&queens), material_phase(&traded));
+        assert!(before > after && after > 0, "{} {}", before, after);
+
+        // Blended by the material left, the lead barely moves
+        let step = lead(&traded, after) - lead(&queens, before);
+        // Switching from the middlegame to the endgame tables at once would not
+        let jump = lead(&traded, 0) - lead(&queens, PHASE_MAX);
+        assert!(step.abs() < 0.3, "{}", step);
+        assert!(jump.abs() > 2.0 * step.abs(), "{} against {}", jump, step);
+    }
+
+    #[test]
+    fn finished_games_score_without_contempt() {
+        assert_eq!(
+            adjudicate(&board("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1")),
+            Score::DRAW
+        );
+        assert_eq!(
+            adjudicate(&board("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1")),
+            Score::mated_in(0)
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use crate::movegen::Position;
+use crate::send;
+use std::time::Instant;
+
+// Leaf nodes of the legal move tree to the given depth. Plays the moves the
+// way the search does, on clones through make_move, so it checks that path too.
+pub fn perft(position: &Position, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = position.generate_legal_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    moves
+        .iter()
+        .map(|mv| {
+            let mut next = position.clone();
+            next.make_move(mv);
+            perft(&next, depth - 1)
+        })
+        .sum()
+}
+
+// The leaves under each root move at the given depth, "divide" in other
+// engines, to find a wrong move by diffing against them. Then the totals of
+// every depth up to it.
+pub fn perft_report(position: &Position, depth: u32) {
+    let start_time = Instant::now();
+    let mut total = 0;
+    for mv in position.generate_legal_moves() {
+        let mut next = position.clone();
+        next.make_move(&mv);
+        let nodes = perft(&next, depth.saturating_sub(1));
+        total += nodes;
+        send!("{}: {}", mv, nodes);
+    }
+    send!("");
+    for shallower in 1..depth {
+        send!("Depth {}: {}", shallower, perft(position, shallower));
+    }
+    send!("Depth {}: {}", depth, total);
+
+    let elapsed = start_time.elapsed();
+    send!("Total time (ms)  : {}", elapsed.as_millis());
+    send!(
+        "Nodes/sec        : {:.0}",
+        total as f64 / elapsed.as_secs_f64().max(1e-9)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defs::FEN_START;
+
+    // Counts from the chess programming wiki's perft results page
+    fn check(fen: &str, expected: &[u64]) {
+        let position = Position::from_fen(fen).unwrap();
+        for (depth, &nodes) in (1..).zip(expected) {
+            assert_eq!(perft(&position, depth), nodes, "{} depth {}", fen, depth);
+        }
+    }
+
+    #[test]
+    fn start_position_counts() {
+        check(FEN_START, &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn kiwipete_counts() {
+        // Castling, en passant, promotions and pins all at once
+        check(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862],
+        );
+    }
+
+    #[test]
+    fn rook_endgame_counts() {
+        // Discovered checks along the rank and en passant out of check
+        check(
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238],
+        );
+    }
+}
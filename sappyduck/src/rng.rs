@@ -0,0 +1,61 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Seed every randomized feature starts from, set through the Seed option so a
+// run can be reproduced exactly
+pub const DEFAULT_SEED: u64 = 0x5EED_D0C5;
+
+static SEED: AtomicU64 = AtomicU64::new(DEFAULT_SEED);
+
+pub fn set_seed(seed: u64) {
+    SEED.store(seed, Ordering::SeqCst);
+}
+
+pub fn seed() -> u64 {
+    SEED.load(Ordering::Relaxed)
+}
+
+// xorshift64*, small and fast, plenty for picking moves or adding eval noise.
+// Not for anything that needs unpredictable numbers.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The all-zero state would only ever produce zeros
+        Rng {
+            state: if seed == 0 { DEFAULT_SEED } else { seed },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // Uniform in 0..bound, bound must not be 0
+    pub fn below(&mut self, bound: u64) -> u64 {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draws(seed: u64) -> Vec<u64> {
+        let mut rng = Rng::new(seed);
+        (0..100).map(|_| rng.below(1000)).collect()
+    }
+
+    #[test]
+    fn same_seed_same_numbers() {
+        assert_eq!(draws(42), draws(42));
+        assert_ne!(draws(42), draws(43));
+        assert!(draws(7).iter().all(|&n| n < 1000));
+        // A zero seed still gives numbers, the same as the default seed
+        assert_eq!(draws(0), draws(DEFAULT_SEED));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::defs::MAX_PLY;
+use std::ops::{Add, Mul, Neg, Sub};
+
+// A search score in centipawns. The evaluation terms work in pawns as f64 and
+// are converted once, everything from the static eval up through the search,
+// the transposition table and the info lines uses this.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub struct Score(pub i32);
+
+impl Score {
+    pub const DRAW: Score = Score(0);
+    // Being mated n plies from the root scores -(MATE - n), mating +(MATE - n)
+    pub const MATE: Score = Score(1_000_000);
+    // Scores past this, either way, are mates
+    pub const MATE_BOUND: Score = Score(Self::MATE.0 - MAX_PLY);
+    // Outside every real score, for an open window
+    pub const INFINITY: Score = Score(Self::MATE.0 + 1);
+
+    pub fn from_pawns(pawns: f64) -> Score {
+        Score((pawns * 100.0).round() as i32)
+    }
+
+    pub fn centipawns(self) -> i32 {
+        self.0
+    }
+
+    pub fn mated_in(ply: i32) -> Score {
+        Score(-(Self::MATE.0 - ply))
+    }
+
+    pub fn mate_in(ply: i32) -> Score {
+        Score(Self::MATE.0 - ply)
+    }
+
+    pub fn is_mate(self) -> bool {
+        self.0.abs() >= Self::MATE_BOUND.0
+    }
+
+    // Moves, not plies, to the mate as UCI wants them, negative when getting mated
+    pub fn mate_moves(self) -> Option<i32> {
+        if !self.is_mate() {
+            None
+        } else if self.0 > 0 {
+            Some((Self::MATE.0 - self.0 + 1) / 2)
+        } else {
+            Some(-(Self::MATE.0 + self.0) / 2)
+        }
+    }
+
+    // Mate scores count plies from the root, the table keeps them counted from
+    // the node instead so an entry reads right whatever ply it is found at again
+    pub fn to_tt(self, ply: i32) -> Score {
+        match self {
+            score if score >= Self::MATE_BOUND => Score(score.0 + ply),
+            score if score <= -Self::MATE_BOUND => Score(score.0 - ply),
+            score => score,
+        }
+    }
+
+    pub fn from_tt(stored: Score, ply: i32) -> Score {
+        match stored {
+            score if score >= Self::MATE_BOUND => Score(score.0 - ply),
+            score if score <= -Self::MATE_BOUND => Score(score.0 + ply),
+            score => score,
+        }
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score {
+        Score(-self.0)
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+    fn add(self, other: Score) -> Score {
+        Score(self.0 + other.0)
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+    fn sub(self, other: Score) -> Score {
+        Score(self.0 - other.0)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, factor: i32) -> Score {
+        Score(self.0 * factor)
+    }
+}
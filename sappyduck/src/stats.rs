@@ -0,0 +1,72 @@
+use crate::movepick::SearchParams;
+use std::sync::Mutex;
+
+// Diagnostics of the last finished search, for external tooling
+pub struct SearchStats {
+    pub depth: i32,
+    pub seldepth: i32,
+    pub nodes: u64,
+    pub qnodes: u64,
+    pub time_ms: u128,
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub beta_cutoffs: u64,
+    pub pv: Vec<String>,
+}
+
+static LAST_STATS: Mutex<Option<SearchStats>> = Mutex::new(None);
+
+// Remember the counters of a search once it is over, never called per node
+pub fn record_search(params: &SearchParams, depth: i32, pv: Vec<String>) {
+    let stats = SearchStats {
+        depth,
+        seldepth: params.seldepth,
+        nodes: params.nodes,
+        qnodes: params.qnodes,
+        time_ms: params.start_time.elapsed().as_millis(),
+        tt_probes: params.tt_probes,
+        tt_hits: params.tt_hits,
+        beta_cutoffs: params.beta_cutoffs,
+        pv,
+    };
+    *LAST_STATS.lock().unwrap() = Some(stats);
+}
+
+pub fn last_search_json() -> Option<String> {
+    LAST_STATS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(SearchStats::to_json)
+}
+
+impl SearchStats {
+    pub fn nps(&self) -> u64 {
+        (self.nodes as u128 * 1000 / self.time_ms.max(1)) as u64
+    }
+
+    // Single line JSON object, the moves are plain coordinate strings so need no escaping
+    pub fn to_json(&self) -> String {
+        let ratio = |part: u64, total: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                part as f64 / total as f64
+            }
+        };
+        let pv: Vec<String> = self.pv.iter().map(|mv| format!("\"{}\"", mv)).collect();
+
+        format!(
+            "{{\"depth\":{},\"seldepth\":{},\"nodes\":{},\"qnodes\":{},\"nps\":{},\"time\":{},\"tt_hit_rate\":{:.4},\"cutoff_rate\":{:.4},\"pv\":[{}]}}",
+            self.depth,
+            self.seldepth,
+            self.nodes,
+            self.qnodes,
+            self.nps(),
+            self.time_ms,
+            ratio(self.tt_hits, self.tt_probes),
+            ratio(self.beta_cutoffs, self.nodes),
+            pv.join(",")
+        )
+    }
+}
@@ -1,14 +1,18 @@
 // sappy: borrowed from walleye: https://github.com/MitchelPaulin/Walleye
+use crate::defs::MAX_PLY;
 use crate::movegen::Position;
-use crate::movepick::pick_move;
+use crate::movepick::{pick_move_instantly, search_position, SearchParams, SearchResult};
+use crate::uci::is_pondering;
 use chess::Color;
-use std::time::Instant;
+use std::time::Duration;
 
 pub const SAFEGUARD: f64 = 100.0; // msecs
 const GAME_LENGTH: u32 = 30; // moves
 const MAX_USAGE: f64 = 0.8; // percentage
 const NO_TIME: u128 = 0;
+const PANIC_TIME: u128 = 10; // msecs, below this there's no time to search at all
 
+#[derive(Default)]
 pub struct GameTime {
     // all time is in ms unless otherwise specified
     pub wtime: i128,
@@ -25,33 +29,157 @@ pub struct GameTime {
 impl GameTime {
     // Calculates the time the engine allocates for searching a single
     // move. This depends on the number of moves still to go in the game.
+    // The increment comes on top of a share of the clock, but it's only
+    // credited after the move, so the slice never takes more than MAX_USAGE
+    // of what's on the clock now. That keeps increment-only games (0+1 and
+    // the like) from flagging while still using the increment.
     pub fn calculate_time(&self, color: Color) -> u128 {
         let mtg = self.movestogo.unwrap_or(GAME_LENGTH) as f64;
         let is_white = color == Color::White;
         let clock = if is_white { self.wtime } else { self.btime } as f64;
         let increment = if is_white { self.winc } else { self.binc } as f64;
-        let base_time = clock - SAFEGUARD;
+        let base_time = (clock - SAFEGUARD).max(0.0);
 
         // return a time slice.
-        if base_time <= 0.0 {
-            if increment > 0.0 {
-                (increment * MAX_USAGE).round() as u128
-            } else {
-                NO_TIME
-            }
+        let slice = base_time * MAX_USAGE / mtg + increment * MAX_USAGE;
+        let limit = clock * MAX_USAGE;
+        if slice <= 0.0 || limit <= 0.0 {
+            NO_TIME
         } else {
-            (base_time * MAX_USAGE / mtg).round() as u128
+            slice.min(limit).round() as u128
         }
     }
 }
 
-pub fn pick_move_timed(position: &mut Position, time_slice: u128) -> Option<String> {
-    // Placeholder for move picking logic with time control
-    // Implement your move picking logic here
-    // For now, just return the first legal move
-    let start_time = Instant::now();
-    while start_time.elapsed().as_millis() < time_slice {
-        // Simulate thinking process
+// Everything a go command can limit the search by. All given limits apply at
+// once and the search stops at whichever is reached first.
+#[derive(Default)]
+pub struct SearchLimits {
+    pub time: GameTime,
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<u128>, // ms
+    pub mate: Option<i32>,      // moves
+    pub searchmoves: Vec<String>,
+    pub infinite: bool,
+    pub ponder: bool,
+}
+
+impl SearchLimits {
+    // Only a depth was given, nothing else to stop the search. Not when
+    // pondering, the answer has to wait for ponderhit or stop.
+    pub fn depth_only(&self) -> bool {
+        self.depth.is_some()
+            && self.nodes.is_none()
+            && self.mate.is_none()
+            && self.movetime.is_none()
+            && !self.infinite
+            && !self.ponder
+            && !self.has_clock()
+    }
+
+    fn has_clock(&self) -> bool {
+        self.time.wtime > 0 || self.time.btime > 0 || self.time.winc > 0 || self.time.binc > 0
+    }
+
+    // The time to search: movetime when given, it's a deadline the GUI has
+    // already worked out, else the clock's time slice. Without either there's
+    // no deadline at all, the other limits or stop end the search.
+    pub fn max_time(&self, color: Color) -> Duration {
+        let limit = match self.movetime {
+            Some(movetime) => movetime,
+            None if self.has_clock() && !self.infinite => self.time.calculate_time(color),
+            None => return Duration::MAX,
+        };
+        Duration::from_millis(limit as u64)
+    }
+}
+
+// Searches within the limits. A ponder search keeps going past its time until
+// ponderhit, after which it gets the full time again. With the clock all but
+// gone the move is picked without searching, so the engine doesn't flag.
+pub fn search_with_limits(position: &mut Position, limits: &SearchLimits) -> SearchResult {
+    let max_time = limits.max_time(position.board.side_to_move());
+    if max_time.as_millis() < PANIC_TIME && !limits.ponder {
+        return pick_move_instantly(position);
+    }
+
+    // A mate in n moves takes 2n - 1 plies, no need to look deeper for it
+    let mate_depth = limits.mate.map_or(MAX_PLY, |moves| 2 * moves.max(1) - 1);
+    let mut params = SearchParams {
+        max_time,
+        max_depth: limits.depth.unwrap_or(MAX_PLY).min(mate_depth),
+        mate_moves: limits.mate,
+        root_moves: limits.searchmoves.clone(),
+        max_nodes: limits.nodes,
+        pondering: limits.ponder && is_pondering(),
+        ..SearchParams::default()
+    };
+    search_position(position, &mut params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn increment_only(clock: i128, increment: i128) -> GameTime {
+        GameTime {
+            wtime: clock,
+            btime: clock,
+            winc: increment,
+            binc: increment,
+            movestogo: None,
+        }
+    }
+
+    #[test]
+    fn increment_only_games_spend_about_the_increment() {
+        // 0+1 a few moves in, the clock hovering around the increment
+        let slice = increment_only(1000, 1000).calculate_time(Color::White);
+        assert!((500..1000).contains(&slice), "{} ms", slice);
+
+        // Move after move the clock never runs out
+        let mut clock = 1000;
+        for _ in 0..200 {
+            let spent = increment_only(clock, 1000).calculate_time(Color::Black) as i128;
+            assert!(spent < clock, "{} ms of {}", spent, clock);
+            clock += 1000 - spent;
+        }
+
+        // Nothing on the clock yet, the increment only comes after the move
+        assert_eq!(
+            increment_only(0, 1000).calculate_time(Color::White),
+            NO_TIME
+        );
+    }
+
+    #[test]
+    fn moves_to_go_shares_out_the_clock() {
+        let mut time = increment_only(10_000, 0);
+        time.movestogo = Some(1);
+        let last_move = time.calculate_time(Color::White);
+        time.movestogo = Some(20);
+        let early_move = time.calculate_time(Color::White);
+        assert!(last_move > early_move, "{} {}", last_move, early_move);
+        assert!(last_move < 10_000);
+    }
+
+    #[test]
+    fn no_time_limit_means_no_deadline() {
+        let nodes_only = SearchLimits {
+            nodes: Some(1000),
+            ..SearchLimits::default()
+        };
+        assert_eq!(nodes_only.max_time(Color::White), Duration::MAX);
+
+        // infinite ignores the clock, but not a movetime
+        let mut infinite = SearchLimits {
+            time: increment_only(60_000, 0),
+            infinite: true,
+            ..SearchLimits::default()
+        };
+        assert_eq!(infinite.max_time(Color::Black), Duration::MAX);
+        infinite.movetime = Some(250);
+        assert_eq!(infinite.max_time(Color::Black), Duration::from_millis(250));
     }
-    pick_move(position)
 }
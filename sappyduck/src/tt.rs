@@ -0,0 +1,316 @@
+use crate::score::Score;
+use chess::{ChessMove, Piece, ALL_SQUARES};
+use lazy_static::lazy_static;
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+// Table size when nothing else is asked for, and the range of the Hash option
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MIN_HASH_MB: usize = 1;
+pub const MAX_HASH_MB: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bound {
+    Exact,
+    Lower, // the score failed high, the real one is at least this
+    Upper, // the score failed low, the real one is at most this
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TtEntry {
+    pub key: u64,
+    pub depth: i32,
+    pub score: Score,
+    pub flag: Bound,
+    pub best_move: Option<ChessMove>,
+}
+
+// One slot of the table. Both words are read and written with plain atomic
+// loads and stores and no lock, so two threads storing into the same slot can
+// leave the key of one next to the data of the other. The key is kept xor'ed
+// with the data: a torn slot then fails the key check and reads as a miss.
+struct Slot {
+    check: AtomicU64, // key ^ data
+    data: AtomicU64,
+}
+
+// Shared by every search thread. Probes and stores index the slots directly,
+// without a lock, so they never block each other. The slot array itself only
+// changes in resize, which runs while no search is using the table.
+pub struct TranspositionTable {
+    slots: UnsafeCell<Vec<Slot>>,
+    generation: AtomicU8, // bumped by every search, stamped on what it stores
+}
+
+// The slots are atomics, only resize replaces the array and it requires that
+// no other thread is using the table
+unsafe impl Sync for TranspositionTable {}
+
+// Slots looked at for hashfull, like Stockfish does
+const HASHFULL_SAMPLE: usize = 1000;
+
+lazy_static! {
+    pub static ref TT: TranspositionTable = TranspositionTable::new(DEFAULT_HASH_MB);
+}
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> Self {
+        TranspositionTable {
+            slots: UnsafeCell::new(allocate(megabytes)),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    /// Replace the table with an empty one of the new size.
+    ///
+    /// # Safety
+    ///
+    /// No other thread may be using the table, in particular no search may be
+    /// running.
+    pub unsafe fn resize(&self, megabytes: usize) {
+        *self.slots.get() = allocate(megabytes);
+    }
+
+    fn slots(&self) -> &[Slot] {
+        // Only resize writes the array, and never while this borrow is alive
+        unsafe { &*self.slots.get() }
+    }
+
+    // Start a new search, entries stored from now on belong to it
+    pub fn new_search(&self) {
+        let next = (self.generation.load(Ordering::Relaxed) + 1) & GENERATION_MASK;
+        self.generation.store(next, Ordering::Relaxed);
+    }
+
+    // Per mille of the sampled slots holding an entry of the current search.
+    // Counted from the table itself each time, so it can't drift.
+    pub fn hashfull(&self) -> u32 {
+        let generation = self.generation.load(Ordering::Relaxed) as u64;
+        let slots = self.slots();
+        let sample = &slots[..slots.len().min(HASHFULL_SAMPLE)];
+        if sample.is_empty() {
+            return 0;
+        }
+        let used = sample
+            .iter()
+            .filter(|slot| {
+                let data = slot.data.load(Ordering::Relaxed);
+                data & USED_BIT != 0 && (data >> GENERATION_SHIFT) == generation
+            })
+            .count();
+        (used * 1000 / sample.len()) as u32
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        let slots = self.slots();
+        // Multiply-shift maps the key onto any table size without a division
+        let index = ((key as u128 * slots.len() as u128) >> 64) as usize;
+        &slots[index]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+        let check = slot.check.load(Ordering::Relaxed);
+        if data & USED_BIT == 0 || check ^ data != key {
+            return None;
+        }
+        Some(unpack(key, data))
+    }
+
+    // Depth-preferred: an entry for the same position is only replaced by one
+    // searched at least as deep, any other position takes the slot
+    pub fn store(&self, entry: TtEntry) {
+        if let Some(old) = self.probe(entry.key) {
+            if old.depth > entry.depth {
+                return;
+            }
+        }
+        let generation = self.generation.load(Ordering::Relaxed) as u64;
+        let data = pack(&entry) | generation << GENERATION_SHIFT;
+        let slot = self.slot(entry.key);
+        slot.check.store(entry.key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for slot in self.slots() {
+            slot.check.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+// Zeroed slots for a table of the given size, at least one
+fn allocate(megabytes: usize) -> Vec<Slot> {
+    let count = (megabytes * 1024 * 1024 / std::mem::size_of::<Slot>()).max(1);
+    (0..count)
+        .map(|_| Slot {
+            check: AtomicU64::new(0),
+            data: AtomicU64::new(0),
+        })
+        .collect()
+}
+
+// Data word layout, from the low bits up:
+//   32 score (centipawns) | 8 depth | 2 bound | 16 move | 1 used | 5 generation
+const DEPTH_SHIFT: u32 = 32;
+const BOUND_SHIFT: u32 = 40;
+const MOVE_SHIFT: u32 = 42;
+const USED_BIT: u64 = 1 << 58;
+const GENERATION_SHIFT: u32 = 59;
+const GENERATION_MASK: u8 = 0x1F;
+
+fn pack(entry: &TtEntry) -> u64 {
+    let bound = match entry.flag {
+        Bound::Exact => 0,
+        Bound::Lower => 1,
+        Bound::Upper => 2,
+    };
+    entry.score.0 as u32 as u64
+        | (entry.depth.clamp(0, 255) as u64) << DEPTH_SHIFT
+        | bound << BOUND_SHIFT
+        | (entry.best_move.map_or(0, pack_move) as u64) << MOVE_SHIFT
+        | USED_BIT
+}
+
+fn unpack(key: u64, data: u64) -> TtEntry {
+    TtEntry {
+        key,
+        depth: ((data >> DEPTH_SHIFT) & 0xFF) as i32,
+        score: Score(data as u32 as i32),
+        flag: match (data >> BOUND_SHIFT) & 3 {
+            1 => Bound::Lower,
+            2 => Bound::Upper,
+            _ => Bound::Exact,
+        },
+        best_move: unpack_move(((data >> MOVE_SHIFT) & 0xFFFF) as u16),
+    }
+}
+
+// 6 bits source, 6 bits destination, 3 bits promotion piece, top bit set for a move
+fn pack_move(mv: ChessMove) -> u16 {
+    let promotion = match mv.get_promotion() {
+        Some(Piece::Knight) => 1,
+        Some(Piece::Bishop) => 2,
+        Some(Piece::Rook) => 3,
+        Some(Piece::Queen) => 4,
+        _ => 0,
+    };
+    0x8000
+        | mv.get_source().to_index() as u16
+        | (mv.get_dest().to_index() as u16) << 6
+        | promotion << 12
+}
+
+fn unpack_move(bits: u16) -> Option<ChessMove> {
+    if bits & 0x8000 == 0 {
+        return None;
+    }
+    let promotion = match (bits >> 12) & 7 {
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => None,
+    };
+    Some(ChessMove::new(
+        ALL_SQUARES[(bits & 0x3F) as usize],
+        ALL_SQUARES[((bits >> 6) & 0x3F) as usize],
+        promotion,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_stores_never_read_back_torn() {
+        let table = TranspositionTable::new(MIN_HASH_MB);
+        table.new_search();
+        // Keys that share their top bits share a slot, so every thread keeps
+        // overwriting the others. Each key's entry is made from the key alone,
+        // anything else read back under it is a torn slot let through.
+        let key = |thread: u64| 0xABCD_0000_0000_0000 | (thread * 0x1_0001);
+        let entry = |thread: u64| TtEntry {
+            key: key(thread),
+            depth: thread as i32,
+            score: Score(thread as i32 * 1001),
+            flag: Bound::Lower,
+            best_move: None,
+        };
+
+        std::thread::scope(|scope| {
+            for thread in 1..=4 {
+                let table = &table;
+                scope.spawn(move || {
+                    for _ in 0..20_000 {
+                        table.store(entry(thread));
+                        for other in 1..=4 {
+                            if let Some(found) = table.probe(key(other)) {
+                                let expected = entry(other);
+                                assert_eq!(found.depth, expected.depth);
+                                assert_eq!(found.score, expected.score);
+                                assert_eq!(found.flag, expected.flag);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn hashfull_counts_the_current_search_in_the_sample() {
+        let table = TranspositionTable::new(MIN_HASH_MB);
+        table.new_search();
+        let slots = MIN_HASH_MB * 1024 * 1024 / std::mem::size_of::<Slot>();
+        // The smallest key landing in each slot, for the first 500 slots past the first
+        let key = |slot: usize| ((slot as u128) << 64).div_ceil(slots as u128) as u64;
+        for slot in 1..=500 {
+            table.store(TtEntry {
+                key: key(slot),
+                depth: 1,
+                score: Score(0),
+                flag: Bound::Exact,
+                best_move: None,
+            });
+        }
+        assert_eq!(table.hashfull(), 500);
+
+        // Entries of an earlier search don't count
+        table.new_search();
+        assert_eq!(table.hashfull(), 0);
+        assert!(table.probe(key(1)).is_some());
+    }
+
+    #[test]
+    fn deeper_entries_stay_until_cleared() {
+        let table = TranspositionTable::new(MIN_HASH_MB);
+        let entry = |depth, score| TtEntry {
+            key: 0x1234_5678_9ABC_DEF0,
+            depth,
+            score: Score(score),
+            flag: Bound::Upper,
+            best_move: Some(ChessMove::new(ALL_SQUARES[12], ALL_SQUARES[28], None)),
+        };
+        table.new_search();
+        table.store(entry(3, 30));
+        let found = table.probe(entry(3, 30).key).unwrap();
+        assert_eq!(
+            (found.depth, found.score, found.flag),
+            (3, Score(30), Bound::Upper)
+        );
+        assert_eq!(found.best_move, entry(3, 30).best_move);
+
+        // Shallower searches of the same position don't replace it, deeper ones do
+        table.store(entry(2, 20));
+        assert_eq!(table.probe(entry(3, 30).key).unwrap().score, Score(30));
+        table.store(entry(5, 50));
+        assert_eq!(table.probe(entry(3, 30).key).unwrap().score, Score(50));
+
+        table.clear();
+        assert!(table.probe(entry(3, 30).key).is_none());
+    }
+}
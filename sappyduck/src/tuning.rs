@@ -0,0 +1,129 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+// A numeric search or evaluation knob that a tuner can change at runtime
+// through setoption, kept as an integer so it maps onto a UCI spin option
+pub struct TuningParam {
+    pub name: &'static str,
+    pub default: i32,
+    pub min: i32,
+    pub max: i32,
+    value: AtomicI32,
+}
+
+impl TuningParam {
+    const fn new(name: &'static str, default: i32, min: i32, max: i32) -> Self {
+        TuningParam {
+            name,
+            default,
+            min,
+            max,
+            value: AtomicI32::new(default),
+        }
+    }
+
+    pub fn get(&self) -> i32 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    // Values given in centipawns, read back in pawns like the rest of the eval
+    pub fn pawns(&self) -> f64 {
+        self.get() as f64 / 100.0
+    }
+
+    // Percentages read back as a multiplier
+    pub fn factor(&self) -> f64 {
+        self.get() as f64 / 100.0
+    }
+
+    // Returns false and keeps the old value when out of range
+    pub fn set(&self, value: i32) -> bool {
+        if value < self.min || value > self.max {
+            return false;
+        }
+        self.value.store(value, Ordering::Relaxed);
+        true
+    }
+}
+
+// Search
+pub static ASPIRATION_WINDOW: TuningParam = TuningParam::new("AspirationWindow", 50, 1, 1000);
+pub static ASPIRATION_MIN_DEPTH: TuningParam = TuningParam::new("AspirationMinDepth", 4, 1, 64);
+pub static PERPETUAL_SEEK_MARGIN: TuningParam =
+    TuningParam::new("PerpetualSeekMargin", 200, 0, 2000);
+// Material lead (either side) past which quiet nodes are searched a ply
+// shallower, 0 turns it off
+pub static FAST_TRACK_MARGIN: TuningParam = TuningParam::new("FastTrackMargin", 1500, 0, 10000);
+// Null-move pruning searches the reply to a pass this many plies shallower,
+// on top of the ply the pass itself takes, from this remaining depth on
+pub static NULL_MOVE_REDUCTION: TuningParam = TuningParam::new("NullMoveReduction", 2, 0, 6);
+pub static NULL_MOVE_MIN_DEPTH: TuningParam = TuningParam::new("NullMoveMinDepth", 3, 1, 64);
+// Late move reductions start from this move index and remaining depth. The
+// reduced search keeps a ply, so there's nothing to take off below depth 3.
+pub static LMR_MIN_MOVES: TuningParam = TuningParam::new("LmrMinMoves", 3, 1, 256);
+pub static LMR_MIN_DEPTH: TuningParam = TuningParam::new("LmrMinDepth", 3, 3, 64);
+// Centipawns a draw is worth less than equality to the engine in the search
+pub static CONTEMPT: TuningParam = TuningParam::new("Contempt", 0, -100, 100);
+
+// Evaluation weights
+pub static KNIGHT_BLOCKADE_BONUS: TuningParam = TuningParam::new("KnightBlockadeBonus", 30, 0, 200);
+pub static BACKWARD_PAWN_PENALTY: TuningParam = TuningParam::new("BackwardPawnPenalty", 10, 0, 200);
+
+// Bound on the static eval, below Score::MATE_BOUND
+pub static EVAL_LIMIT: TuningParam = TuningParam::new("EvalLimit", 900_000, 100, 990_000);
+
+// Evaluation group weights in percent, also set together by the Style option
+pub static MATERIAL_WEIGHT: TuningParam = TuningParam::new("MaterialWeight", 100, 0, 200);
+pub static PAWN_STRUCTURE_WEIGHT: TuningParam =
+    TuningParam::new("PawnStructureWeight", 100, 0, 200);
+pub static KING_ATTACK_WEIGHT: TuningParam = TuningParam::new("KingAttackWeight", 100, 0, 200);
+
+pub static TUNING_PARAMS: [&TuningParam; 15] = [
+    &ASPIRATION_WINDOW,
+    &ASPIRATION_MIN_DEPTH,
+    &PERPETUAL_SEEK_MARGIN,
+    &FAST_TRACK_MARGIN,
+    &NULL_MOVE_REDUCTION,
+    &NULL_MOVE_MIN_DEPTH,
+    &LMR_MIN_MOVES,
+    &LMR_MIN_DEPTH,
+    &CONTEMPT,
+    &KNIGHT_BLOCKADE_BONUS,
+    &BACKWARD_PAWN_PENALTY,
+    &EVAL_LIMIT,
+    &MATERIAL_WEIGHT,
+    &PAWN_STRUCTURE_WEIGHT,
+    &KING_ATTACK_WEIGHT,
+];
+
+// Playing styles as (name, material, pawn structure, king attack) weights.
+// Balanced keeps every group at 100%.
+pub const STYLES: [(&str, i32, i32, i32); 4] = [
+    ("Balanced", 100, 100, 100),
+    ("Aggressive", 90, 90, 130),
+    ("Positional", 100, 125, 90),
+    ("Solid", 110, 110, 80),
+];
+
+// Set the group weights of a style, false if there's no style by that name
+pub fn apply_style(name: &str) -> bool {
+    match STYLES
+        .iter()
+        .find(|style| style.0.eq_ignore_ascii_case(name))
+    {
+        Some(&(_, material, pawn_structure, king_attack)) => {
+            MATERIAL_WEIGHT.set(material);
+            PAWN_STRUCTURE_WEIGHT.set(pawn_structure);
+            KING_ATTACK_WEIGHT.set(king_attack);
+            true
+        }
+        None => false,
+    }
+}
+
+// Case-insensitive lookup, as UCI option names are
+pub fn find_param(name: &str) -> Option<&'static TuningParam> {
+    TUNING_PARAMS
+        .iter()
+        .copied()
+        .find(|param| param.name.eq_ignore_ascii_case(name))
+}
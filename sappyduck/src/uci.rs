@@ -1,108 +1,303 @@
-use crate::defs::FEN_START;
+use crate::bench::{bench_eval, bench_search, BENCH_DEPTH};
+use crate::defs::{FEN_START, MAX_DEPTH};
 use crate::movegen::Position;
-use crate::movepick::{alpha_beta_search, evaluate_board, pick_move, SearchParams}; // Added alpha_beta_search
-use crate::time_control::{pick_move_timed, GameTime};
-use chess::Color;
-use std::io::{self, BufRead};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use crate::movepick::{
+    adjudicate, alpha_beta_search, eval_report, hanging_piece, multi_pv_lines, prepare_helpers,
+    principal_variation, root_moves, run_with_helpers, send_lines, set_analyse_mode, set_fast_eval,
+    set_multi_pv, set_node_stats, set_opening_ordering, set_qsearch_checks, set_threads,
+    SearchParams, SearchResult, MAX_MULTI_PV, MAX_THREADS,
+};
+use crate::perft::perft_report;
+use crate::rng::{set_seed, DEFAULT_SEED};
+use crate::score::Score;
+use crate::stats::{last_search_json, record_search};
+use crate::time_control::{search_with_limits, SearchLimits};
+use crate::tt::{DEFAULT_HASH_MB, MAX_HASH_MB, MIN_HASH_MB, TT};
+use crate::tuning::{apply_style, find_param, STYLES, TUNING_PARAMS};
+use chess::{ChessMove, Color, Square, ALL_FILES, ALL_RANKS};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
+// CommandLog: every command received and line sent, for session transcripts
+// to attach to bug reports. None while it's off.
+static COMMAND_LOG: Mutex<Option<File>> = Mutex::new(None);
+
+// Print a line to the GUI, and to the command log when one is open
+#[macro_export]
+macro_rules! send {
+    ($($arg:tt)*) => {{
+        // Logged first, once the GUI has the line it may already be closing the log
+        let line = format!($($arg)*);
+        $crate::uci::log_line("<", &line);
+        println!("{}", line);
+    }};
+}
+
+// Lines are marked > for received and < for sent
+pub fn log_line(direction: &str, line: &str) {
+    if let Some(file) = COMMAND_LOG.lock().unwrap().as_mut() {
+        let _ = writeln!(file, "{} {}", direction, line);
+    }
+}
+
+// An empty path closes the log, anything else is appended to
+fn set_command_log(path: &str) {
+    let mut log = COMMAND_LOG.lock().unwrap();
+    *log = None;
+    if path.is_empty() || path == "<empty>" {
+        return;
+    }
+    match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(file) => *log = Some(file),
+        Err(err) => println!("info string can't open command log {}: {}", path, err),
+    }
+}
+
 // Add a static stop flag
 static STOP_FLAG: AtomicBool = AtomicBool::new(false);
 
+// Set while a "go ponder" search waits for ponderhit
+static PONDERING: AtomicBool = AtomicBool::new(false);
+
+// Set when a ponder search is dropped because the opponent played another
+// move, its result is for the wrong position and must not be sent
+static PONDER_MISSED: AtomicBool = AtomicBool::new(false);
+
+// ScorePerspective: print scores from White's point of view instead of the
+// UCI standard side to move's
+static WHITE_PERSPECTIVE: AtomicBool = AtomicBool::new(false);
+
+// Set by "debug on", enables self-checks of the search results
+static DEBUG_MODE: AtomicBool = AtomicBool::new(false);
+
+// Consecutive searches that scored below -ResignThreshold
+static LOSING_STREAK: AtomicU32 = AtomicU32::new(0);
+
+// How many hopeless searches in a row before we signal resignation
+const RESIGN_MOVES: u32 = 3;
+
+// Engine settings changed through setoption
+#[derive(Default)]
+struct UciOptions {
+    resign_threshold: i32, // centipawns, 0 disables resign signaling
+}
+
 // Communicates with the Universal Chess Interface (UCI)
 pub fn uci_loop() {
-    let mut position = Position::from_fen(FEN_START);
-    let mut game_time = GameTime {
-        wtime: 0,
-        btime: 0,
-        winc: 0,
-        binc: 0,
-        movestogo: None,
-    };
+    let mut position = Position::from_fen_or_default(FEN_START);
+    let mut options = UciOptions::default();
+    let mut search_thread: Option<JoinHandle<()>> = None;
     let stdin = io::stdin();
     let mut input = String::new();
 
     loop {
         input.clear();
-        stdin.lock().read_line(&mut input).unwrap();
+        match stdin.lock().read_line(&mut input) {
+            // The GUI closed stdin or a piped command file ran out, same as quit
+            Ok(0) => {
+                stop_search(&mut search_thread);
+                std::process::exit(0);
+            }
+            Ok(_) => {}
+            // Such as a line that isn't UTF-8, skip it and keep reading
+            Err(err) => {
+                send!("info string failed to read input: {}", err);
+                continue;
+            }
+        }
+        log_line(">", input.trim_end_matches(['\r', '\n']));
         let command = input.trim();
 
         match command {
             // UCI protocol commands
             "uci" => {
-                println!("id name SappyDuck");
-                println!("id author sappyduckie");
-                println!("uciok");
+                send!("id name SappyDuck");
+                send!("id author sappyduckie");
+                send!(
+                    "option name Hash type spin default {} min {} max {}",
+                    DEFAULT_HASH_MB,
+                    MIN_HASH_MB,
+                    MAX_HASH_MB
+                );
+                send!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                );
+                send!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTI_PV
+                );
+                send!("option name Ponder type check default false");
+                send!("option name ResignThreshold type spin default 0 min 0 max 10000");
+                send!("option name FastEval type check default false");
+                send!("option name NodeStats type check default false");
+                send!("option name QSearchChecks type check default false");
+                send!("option name OpeningOrdering type check default true");
+                send!("option name CommandLog type string default <empty>");
+                send!("option name UCI_AnalyseMode type check default false");
+                send!(
+                    "option name ScorePerspective type combo default SideToMove var SideToMove var White"
+                );
+                send!("option name Seed type string default {}", DEFAULT_SEED);
+                let styles: Vec<String> = STYLES
+                    .iter()
+                    .map(|style| format!("var {}", style.0))
+                    .collect();
+                send!(
+                    "option name Style type combo default Balanced {}",
+                    styles.join(" ")
+                );
+                // Search and eval knobs for automated tuning
+                for param in TUNING_PARAMS.iter() {
+                    send!(
+                        "option name {} type spin default {} min {} max {}",
+                        param.name,
+                        param.default,
+                        param.min,
+                        param.max
+                    );
+                }
+                send!("uciok");
             }
+            "debug on" => DEBUG_MODE.store(true, Ordering::SeqCst),
+            "debug off" => DEBUG_MODE.store(false, Ordering::SeqCst),
             "isready" => {
-                println!("readyok");
+                send!("readyok");
             }
             "ucinewgame" => {
-                position = Position::from_fen(FEN_START);
+                abandon_ponder_search(&mut search_thread);
+                position = Position::from_fen_or_default(FEN_START);
+                TT.clear();
+                LOSING_STREAK.store(0, Ordering::SeqCst);
             }
-            cmd if cmd.starts_with("position startpos moves") => {
-                position = Position::from_fen(FEN_START);
-                let moves = &cmd[20..];
-                for mv in moves.split_whitespace() {
-                    position.make_move(mv);
+            cmd if cmd.starts_with("setoption ") => {
+                set_option(cmd, &mut options, &mut search_thread);
+            }
+            // Start position, optionally followed by "moves ..."
+            cmd if cmd.starts_with("position startpos") => {
+                abandon_ponder_search(&mut search_thread);
+                position = Position::from_fen_or_default(FEN_START);
+                if let Some((_, moves)) = cmd.split_once(" moves") {
+                    apply_moves(&mut position, moves);
                 }
             }
-            // Plug in the FEN string
+            // Plug in the FEN string, optionally followed by "moves ..."
             cmd if cmd.starts_with("position fen ") => {
-                let fen = &cmd[13..];
-                position = Position::from_fen(fen);
+                abandon_ponder_search(&mut search_thread);
+                let (fen, moves) = cmd[13..].split_once(" moves").unwrap_or((&cmd[13..], ""));
+                position = match Position::from_fen(fen.trim()) {
+                    Ok(new_position) => new_position,
+                    Err(err) => {
+                        send!("info string {}, using the start position", err);
+                        Position::from_fen_or_default(FEN_START)
+                    }
+                };
+                apply_moves(&mut position, moves);
             }
-            // Analyze the position to a certain depth
-            cmd if cmd.starts_with("go depth ") => {
-                let depth = cmd[9..].trim().parse().unwrap_or(1);
-                println!("info string starting search at depth {}", depth);
-
-                // Reset stop flag at start of search
-                STOP_FLAG.store(false, Ordering::SeqCst);
+            // Search with whatever limits the go command gives
+            cmd if cmd.starts_with("go") => {
+                let mut limits = parse_go(cmd);
+                let mut root = position.clone();
+                limits.searchmoves = legal_searchmoves(&root, &limits.searchmoves);
+                let resign_threshold = options.resign_threshold;
 
-                if let Some(best_move) = analyze_position(&mut position, depth) {
-                    println!("bestmove {}", best_move);
+                // Analyze the position to a certain depth
+                if let (Some(depth), true) = (limits.depth, limits.depth_only()) {
+                    send!("info string starting search at depth {}", depth);
+                    start_search(&mut search_thread, false, move || {
+                        let result = analyze_position(&mut root, depth as u32, limits.searchmoves);
+                        report_resign(&result, resign_threshold);
+                        report_blunder(&root, &result);
+                        if let Some(best_move) = result.best_move {
+                            send!("bestmove {}", best_move);
+                        } else {
+                            // Fallback to any legal move if no best move found
+                            if let Some(first_move) = root.generate_legal_moves().first() {
+                                send!("bestmove {}", first_move);
+                            } else {
+                                send!("info string no legal moves available");
+                                send!("bestmove 0000"); // Standard "null move" notation
+                            }
+                        }
+                    });
+                } else if limits.infinite {
+                    start_search(&mut search_thread, limits.ponder, move || {
+                        let result = search_with_limits(&mut root, &limits);
+                        // Even a search that ran out of depths only answers on stop
+                        wait_for_stop();
+                        if ponder_missed() {
+                            return;
+                        }
+                        report_resign(&result, resign_threshold);
+                        report_blunder(&root, &result);
+                        if let Some(best_move) = result.best_move {
+                            send!("bestmove {}", with_ponder_move(&root, best_move));
+                        } else {
+                            send!("bestmove a1a1"); // Null move as fallback
+                        }
+                    });
                 } else {
-                    // Fallback to any legal move if no best move found
-                    if let Some(first_move) = position.generate_legal_moves().first() {
-                        println!("bestmove {}", first_move);
-                    } else {
-                        println!("info string no legal moves available");
-                        println!("bestmove 0000"); // Standard "null move" notation
-                    }
+                    start_search(&mut search_thread, limits.ponder, move || {
+                        let start_time = Instant::now();
+                        let result = search_with_limits(&mut root, &limits);
+                        wait_for_ponderhit();
+                        if ponder_missed() {
+                            return;
+                        }
+                        report_resign(&result, resign_threshold);
+                        report_blunder(&root, &result);
+                        // GUIs expect nothing after the moves on a bestmove line
+                        send!("info string time spent {:?}", start_time.elapsed());
+                        if let Some(best_move) = result.best_move {
+                            send!("bestmove {}", with_ponder_move(&root, best_move));
+                        } else {
+                            send!("bestmove 0000");
+                        }
+                    });
                 }
             }
-            // Analyze a position for a certain amount of time
-            cmd if cmd.starts_with("go") => {
+            // Evaluation speed benchmark, not part of UCI
+            cmd if cmd.starts_with("bench eval") => {
+                let positions = cmd[10..].trim().parse().unwrap_or(20000);
+                bench_eval(positions);
+            }
+            // Fixed-depth search benchmark, its node count a signature of the search
+            cmd if cmd == "bench" || cmd.starts_with("bench ") => {
+                stop_search(&mut search_thread);
                 STOP_FLAG.store(false, Ordering::SeqCst);
-                if cmd.contains("infinite") {
-                    let mut params = SearchParams::default();
-                    params.max_time = Duration::from_secs(3600); // 1 hour for infinite analysis
-                    let best_move = pick_move(&mut position);
-                    if let Some(best_move) = best_move {
-                        println!("bestmove {}", best_move);
-                    } else {
-                        println!("bestmove a1a1"); // Null move as fallback
-                    }
-                } else {
-                    parse_go(cmd, &mut game_time);
-                    let time_slice = game_time.calculate_time(position.board.side_to_move());
-                    let start_time = Instant::now();
-                    let best_move = pick_move_timed(&mut position, time_slice);
-                    let elapsed_time = start_time.elapsed();
-                    if let Some(best_move) = best_move {
-                        println!("bestmove {} (time spent: {:?})", best_move, elapsed_time);
-                    } else {
-                        println!("bestmove (none) (time spent: {:?})", elapsed_time);
-                    }
-                }
+                let depth = cmd[5..].trim().parse().unwrap_or(BENCH_DEPTH);
+                bench_search(depth);
+            }
+            // Move generation check against known leaf counts, not part of UCI
+            cmd if cmd.starts_with("perft") => match cmd[5..].trim().parse::<u32>() {
+                Ok(depth) if depth > 0 => perft_report(&position, depth),
+                _ => send!("info string usage: perft <depth>"),
+            },
+            // Diagnostics of the last search for tooling, not part of UCI
+            "stats json" => match last_search_json() {
+                Some(json) => send!("{}", json),
+                None => send!("info string no search statistics yet"),
+            },
+            // The current position for debugging, not part of UCI
+            "d" => display_position(&position),
+            // Static evaluation of the current position by term, not part of UCI
+            "eval" => eval_report(&position),
+            // Zobrist key of the current position, to check transpositions and
+            // repetitions by hand. It covers side to move, castling and en passant.
+            "hash" => send!("info string hash {:016x}", position.board.get_hash()),
+            // The opponent played the move we were pondering on, turn it into a real search
+            "ponderhit" => {
+                PONDERING.store(false, Ordering::SeqCst);
             }
             "stop" => {
-                STOP_FLAG.store(true, Ordering::SeqCst);
+                stop_search(&mut search_thread);
             }
             "quit" => {
+                stop_search(&mut search_thread);
                 std::process::exit(0);
             }
             _ => {}
@@ -110,94 +305,395 @@ pub fn uci_loop() {
     }
 }
 
-// For now, just pick a move
-fn analyze_position(position: &mut Position, depth: u32) -> Option<String> {
+// ASCII board, white at the bottom, with the FEN and what it doesn't show at a glance
+fn display_position(position: &Position) {
+    send!("  +-----------------+");
+    for rank in ALL_RANKS.iter().rev() {
+        let squares: Vec<String> = ALL_FILES
+            .iter()
+            .map(|&file| {
+                let square = Square::make_square(*rank, file);
+                match (
+                    position.board.piece_on(square),
+                    position.board.color_on(square),
+                ) {
+                    (Some(piece), Some(color)) => piece.to_string(color),
+                    _ => ".".to_string(),
+                }
+            })
+            .collect();
+        send!("{} | {} |", rank.to_index() + 1, squares.join(" "));
+    }
+    send!("  +-----------------+");
+    send!("    a b c d e f g h");
+
+    let fen = position.fen();
+    let side = match position.board.side_to_move() {
+        Color::White => "white",
+        Color::Black => "black",
+    };
+    send!("Side to move : {}", side);
+    send!(
+        "Castling     : {}",
+        fen.split_whitespace().nth(2).unwrap_or("-")
+    );
+    send!("FEN          : {}", fen);
+    send!("Move count   : {}", position.move_count);
+}
+
+// go depth N: deepen one ply at a time up to the requested depth, with a
+// generous time cap in case the depth asked for is out of reach
+fn analyze_position(position: &mut Position, depth: u32, root_moves: Vec<String>) -> SearchResult {
     let mut params = SearchParams::default();
+    params.root_moves = root_moves;
     params.max_time = Duration::from_secs(300); // 5 minutes max per analysis
+    params.max_depth = depth as i32;
+    params.start_time = Instant::now();
+    params.clock_start = params.start_time;
+    params.root_history = position.history.clone();
+    TT.new_search();
+    let helpers = prepare_helpers(position, &params);
+    run_with_helpers(helpers, || analyze_iterations(position, &mut params))
+}
 
-    // Force depth to 1 regardless of input
-    let max_depth = 1;
-    let mut best_move = None;
-    let mut best_score = f64::NEG_INFINITY;
+fn analyze_iterations(position: &mut Position, params: &mut SearchParams) -> SearchResult {
+    let max_depth = params.max_depth.min(MAX_DEPTH);
+    let mut best_score = -Score::INFINITY;
 
-    println!("info string starting analysis at depth {}", max_depth);
+    send!("info string starting analysis at depth {}", max_depth);
 
     // Generate moves first to check if any are available
     let legal_moves = position.generate_legal_moves();
     if legal_moves.is_empty() {
-        println!("info string no legal moves in position");
-        return None;
+        send!("info string no legal moves in position");
+        return SearchResult {
+            best_move: None,
+            score: adjudicate(&position.board),
+        };
     }
 
+    // A move to fall back on if not even depth 1 completes
+    let mut best_move = root_moves(position, params).first().cloned();
     for current_depth in 1..=max_depth {
         params.depth = current_depth;
-        params.start_time = Instant::now();
 
         let (score, mv) = alpha_beta_search(
             position,
             current_depth,
-            f64::NEG_INFINITY,
-            f64::INFINITY,
-            position.board.side_to_move() == Color::White,
-            &mut params,
+            0,
+            -Score::INFINITY,
+            Score::INFINITY,
+            params,
         );
 
+        // An interrupted depth only has a partial result, keep the last completed one
+        if params.stopped {
+            break;
+        }
+
         if mv.is_some() {
             best_move = mv;
             best_score = score;
-            println!(
-                "info depth {} score cp {} nodes {} time {} pv {}",
+            params.pv_move = best_move.clone();
+            let best = (best_score, best_move.clone().unwrap());
+            record_search(
+                params,
                 current_depth,
-                (best_score * 100.0) as i32,
-                params.nodes,
-                params.start_time.elapsed().as_millis(),
-                best_move.as_ref().unwrap()
+                principal_variation(position, &best.1, current_depth),
             );
+            let lines = multi_pv_lines(position, current_depth, best, params);
+            send_lines(position, current_depth, &lines, params);
+        }
+
+        params.poll_stop();
+        if params.stopped {
+            break;
         }
     }
 
-    best_move
+    SearchResult {
+        best_move: best_move.and_then(|mv| mv.parse().ok()),
+        score: best_score,
+    }
 }
 
-// Parse the go command for time control
-fn parse_go(cmd: &str, game_time: &mut GameTime) {
-    let tokens: Vec<&str> = cmd.split_whitespace().collect();
-    let mut i = 1;
-    while i < tokens.len() {
-        match tokens[i] {
-            // White time control
-            "wtime" => {
-                if i + 1 < tokens.len() {
-                    game_time.wtime = tokens[i + 1].parse().unwrap_or(0);
-                }
+// Play the moves of a position command, given in UCI or SAN notation. Stops at
+// the first illegal one since the moves after it can't be made sense of.
+fn apply_moves(position: &mut Position, moves: &str) {
+    for token in moves.split_whitespace() {
+        if let Err(err) = position.try_make_move(token) {
+            send!("info string {}, ignoring the rest", err);
+            break;
+        }
+    }
+}
+
+// Parse "setoption name <id> value <x>" into the engine options
+fn set_option(cmd: &str, options: &mut UciOptions, search_thread: &mut Option<JoinHandle<()>>) {
+    let rest = cmd["setoption ".len()..].trim();
+    let rest = rest.strip_prefix("name ").unwrap_or(rest);
+    let (name, value) = match rest.split_once(" value ") {
+        Some((name, value)) => (name.trim(), value.trim()),
+        // "value" with nothing after it, for clearing a string option
+        None => (rest.strip_suffix(" value").unwrap_or(rest).trim(), ""),
+    };
+
+    match name.to_lowercase().as_str() {
+        "hash" => match value.parse::<usize>() {
+            Ok(mb) if (MIN_HASH_MB..=MAX_HASH_MB).contains(&mb) => {
+                // The table can't be swapped out under a running search
+                stop_search(search_thread);
+                unsafe { TT.resize(mb) }
             }
-            // Black time control
-            "btime" => {
-                if i + 1 < tokens.len() {
-                    game_time.btime = tokens[i + 1].parse().unwrap_or(0);
-                }
+            _ => send!("info string invalid Hash value {}", value),
+        },
+        "threads" => match value.parse::<usize>() {
+            Ok(threads) if (1..=MAX_THREADS).contains(&threads) => set_threads(threads),
+            _ => send!("info string invalid Threads value {}", value),
+        },
+        "multipv" => match value.parse::<usize>() {
+            Ok(lines) if (1..=MAX_MULTI_PV).contains(&lines) => set_multi_pv(lines),
+            _ => send!("info string invalid MultiPV value {}", value),
+        },
+        "resignthreshold" => match value.parse::<i32>() {
+            Ok(cp) if cp >= 0 => options.resign_threshold = cp,
+            _ => send!("info string invalid ResignThreshold value {}", value),
+        },
+        "fasteval" => match value {
+            "true" => set_fast_eval(true),
+            "false" => set_fast_eval(false),
+            _ => send!("info string invalid FastEval value {}", value),
+        },
+        "nodestats" => match value {
+            "true" => set_node_stats(true),
+            "false" => set_node_stats(false),
+            _ => send!("info string invalid NodeStats value {}", value),
+        },
+        "qsearchchecks" => match value {
+            "true" => set_qsearch_checks(true),
+            "false" => set_qsearch_checks(false),
+            _ => send!("info string invalid QSearchChecks value {}", value),
+        },
+        "openingordering" => match value {
+            "true" => set_opening_ordering(true),
+            "false" => set_opening_ordering(false),
+            _ => send!("info string invalid OpeningOrdering value {}", value),
+        },
+        "uci_analysemode" => match value {
+            "true" => set_analyse_mode(true),
+            "false" => set_analyse_mode(false),
+            _ => send!("info string invalid UCI_AnalyseMode value {}", value),
+        },
+        "scoreperspective" => match value.to_lowercase().as_str() {
+            "sidetomove" => WHITE_PERSPECTIVE.store(false, Ordering::SeqCst),
+            "white" => WHITE_PERSPECTIVE.store(true, Ordering::SeqCst),
+            _ => send!("info string invalid ScorePerspective value {}", value),
+        },
+        "commandlog" => set_command_log(value),
+        "seed" => match value.parse::<u64>() {
+            Ok(seed) => set_seed(seed),
+            Err(_) => send!("info string invalid Seed value {}", value),
+        },
+        // Only tells the engine the GUI may send go ponder, nothing to set
+        "ponder" => {}
+        "style" => {
+            if !apply_style(value) {
+                send!("info string invalid Style value {}", value);
             }
-            // White increment
-            "winc" => {
-                if i + 1 < tokens.len() {
-                    game_time.winc = tokens[i + 1].parse().unwrap_or(0);
-                }
+        }
+        _ => match find_param(name) {
+            Some(param) => match value.parse::<i32>() {
+                Ok(v) if param.set(v) => {}
+                _ => send!("info string invalid {} value {}", param.name, value),
+            },
+            None => send!("info string unknown option {}", name),
+        },
+    }
+}
+
+// Signal hopelessness once the score has stayed below the threshold for a few moves.
+// This is only a hint for arbiters, the bestmove is always sent as usual.
+fn report_resign(result: &SearchResult, threshold: i32) {
+    if threshold <= 0 || result.best_move.is_none() {
+        return;
+    }
+
+    if result.score.centipawns() < -threshold {
+        let streak = LOSING_STREAK.fetch_add(1, Ordering::SeqCst) + 1;
+        if streak >= RESIGN_MOVES {
+            send!("info string resign");
+        }
+    } else {
+        LOSING_STREAK.store(0, Ordering::SeqCst);
+    }
+}
+
+// In debug mode, warn when the best move leaves a piece hanging while some other
+// move wouldn't have, to catch eval or search regressions. The move is still played.
+fn report_blunder(root: &Position, result: &SearchResult) {
+    if !DEBUG_MODE.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(best_move) = result.best_move.map(|mv| mv.to_string()) else {
+        return;
+    };
+
+    if let Some(square) = hanging_piece(root, &best_move) {
+        let safe_move = root
+            .generate_legal_moves()
+            .into_iter()
+            .find(|mv| hanging_piece(root, mv).is_none());
+        if let Some(safe_move) = safe_move {
+            send!(
+                "info string warning {} hangs the piece on {}, {} doesn't",
+                best_move,
+                square,
+                safe_move
+            );
+        }
+    }
+}
+
+const GO_KEYWORDS: [&str; 12] = [
+    "searchmoves",
+    "ponder",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+];
+
+// The moves of go searchmoves that are legal at the root, with a warning for
+// each one that isn't. None of them legal searches every move again.
+fn legal_searchmoves(position: &Position, searchmoves: &[String]) -> Vec<String> {
+    let legal_moves = position.generate_legal_moves();
+    searchmoves
+        .iter()
+        .filter(|mv| {
+            let legal = legal_moves.contains(mv);
+            if !legal {
+                send!(
+                    "info string searchmoves: {} is not a legal move, skipping it",
+                    mv
+                );
             }
+            legal
+        })
+        .cloned()
+        .collect()
+}
+
+// Parse the go command into search limits
+fn parse_go(cmd: &str) -> SearchLimits {
+    let mut limits = SearchLimits::default();
+    let mut in_searchmoves = false;
+
+    // Scan one token at a time, only value keywords consume the token after them,
+    // so flags like "infinite" or "ponder" can appear anywhere
+    let mut tokens = cmd.split_whitespace().skip(1);
+    while let Some(token) = tokens.next() {
+        // searchmoves takes every token up to the next keyword
+        if in_searchmoves && !GO_KEYWORDS.contains(&token) {
+            limits.searchmoves.push(token.to_string());
+            continue;
+        }
+        in_searchmoves = token == "searchmoves";
+        match token {
+            // White time control
+            "wtime" => limits.time.wtime = next_value(&mut tokens).unwrap_or(0),
+            // Black time control
+            "btime" => limits.time.btime = next_value(&mut tokens).unwrap_or(0),
+            // White increment
+            "winc" => limits.time.winc = next_value(&mut tokens).unwrap_or(0),
             // Black increment
-            "binc" => {
-                if i + 1 < tokens.len() {
-                    game_time.binc = tokens[i + 1].parse().unwrap_or(0);
-                }
-            }
+            "binc" => limits.time.binc = next_value(&mut tokens).unwrap_or(0),
             // Moves to go
-            "movestogo" => {
-                if i + 1 < tokens.len() {
-                    game_time.movestogo = Some(tokens[i + 1].parse().unwrap_or(0));
-                }
-            }
+            "movestogo" => limits.time.movestogo = Some(next_value(&mut tokens).unwrap_or(0)),
+            "depth" => limits.depth = next_value(&mut tokens),
+            "nodes" => limits.nodes = next_value(&mut tokens),
+            "movetime" => limits.movetime = next_value(&mut tokens),
+            "infinite" => limits.infinite = true,
+            "ponder" => limits.ponder = true,
+            "mate" => limits.mate = next_value(&mut tokens),
+            // Unknown tokens stand alone
             _ => {}
         }
-        i += 2;
+    }
+
+    limits
+}
+
+fn next_value<'a, T: std::str::FromStr>(tokens: &mut impl Iterator<Item = &'a str>) -> Option<T> {
+    tokens.next().and_then(|value| value.parse().ok())
+}
+
+// Searches run on their own thread so stop and ponderhit can still be read from stdin
+fn start_search<F>(search_thread: &mut Option<JoinHandle<()>>, ponder: bool, search: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    stop_search(search_thread);
+    STOP_FLAG.store(false, Ordering::SeqCst);
+    PONDER_MISSED.store(false, Ordering::SeqCst);
+    PONDERING.store(ponder, Ordering::SeqCst);
+    *search_thread = Some(thread::spawn(search));
+}
+
+// Make a running search send its bestmove and wait for the thread to finish
+fn stop_search(search_thread: &mut Option<JoinHandle<()>>) {
+    if let Some(handle) = search_thread.take() {
+        STOP_FLAG.store(true, Ordering::SeqCst);
+        PONDERING.store(false, Ordering::SeqCst);
+        let _ = handle.join();
+    }
+}
+
+// Ponder miss: the GUI sets up a new position while we still ponder, without
+// ponderhit or stop. Drop the search without a bestmove so nothing from the
+// predicted line gets out. Its TT entries stay, they're sound for the positions
+// they were stored for and just get replaced as the new search needs the slots.
+fn abandon_ponder_search(search_thread: &mut Option<JoinHandle<()>>) {
+    if search_thread.is_some() && is_pondering() {
+        PONDER_MISSED.store(true, Ordering::SeqCst);
+        stop_search(search_thread);
+    }
+}
+
+fn ponder_missed() -> bool {
+    PONDER_MISSED.load(Ordering::SeqCst)
+}
+
+// The best move followed by "ponder <reply>", the reply being the move the
+// table holds for the position after it, when there is one and it's legal
+fn with_ponder_move(root: &Position, best_move: ChessMove) -> String {
+    let next = root.board.make_move_new(best_move);
+    let reply = TT
+        .probe(next.get_hash())
+        .and_then(|entry| entry.best_move)
+        .filter(|mv| next.legal(*mv));
+    match reply {
+        Some(reply) => format!("{} ponder {}", best_move, reply),
+        None => best_move.to_string(),
+    }
+}
+
+// A ponder search may only send its bestmove after ponderhit or stop
+fn wait_for_ponderhit() {
+    while is_pondering() && !should_stop() {
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+// go infinite may only send its bestmove after stop, pondering or not
+fn wait_for_stop() {
+    while !should_stop() {
+        thread::sleep(Duration::from_millis(1));
     }
 }
 
@@ -205,3 +701,41 @@ fn parse_go(cmd: &str, game_time: &mut GameTime) {
 pub fn should_stop() -> bool {
     STOP_FLAG.load(Ordering::SeqCst)
 }
+
+pub fn is_pondering() -> bool {
+    PONDERING.load(Ordering::SeqCst)
+}
+
+// Score of an info line, given from the side to move's point of view. Mates
+// are given in moves. The White perspective flips the result rather than the
+// score, since negating a mate score would shift it by a ply.
+pub fn format_score(score: Score, side_to_move: Color) -> String {
+    let sign = if WHITE_PERSPECTIVE.load(Ordering::Relaxed) && side_to_move == Color::Black {
+        -1
+    } else {
+        1
+    };
+    match score.mate_moves() {
+        Some(moves) => format!("mate {}", sign * moves),
+        None => format!("cp {}", sign * score.centipawns()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn go_reads_values_after_flags_and_unknown_tokens() {
+        let limits = parse_go("go infinite wtime 1000");
+        assert!(limits.infinite);
+        assert_eq!(limits.time.wtime, 1000);
+
+        let limits = parse_go("go ponder foo btime 2000 bar winc 10 depth 7");
+        assert!(limits.ponder);
+        assert_eq!(limits.time.btime, 2000);
+        assert_eq!(limits.time.winc, 10);
+        assert_eq!(limits.depth, Some(7));
+        assert!(!limits.infinite);
+    }
+}
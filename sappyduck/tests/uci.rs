@@ -0,0 +1,1145 @@
+// Drives the engine binary over stdin and stdout the way a GUI does
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Longest wait for a line before the test fails instead of hanging
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Engine {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    lines: Receiver<String>,
+}
+
+impl Engine {
+    fn new() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_sappyduck"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("engine starts");
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in stdout.lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let stdin = child.stdin.take();
+        Engine {
+            child,
+            stdin,
+            lines,
+        }
+    }
+
+    fn send(&mut self, command: &str) {
+        let stdin = self.stdin.as_mut().unwrap();
+        writeln!(stdin, "{}", command).unwrap();
+        stdin.flush().unwrap();
+    }
+
+    // Every line up to and including the first one starting with prefix
+    fn read_until(&mut self, prefix: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self
+                .lines
+                .recv_timeout(TIMEOUT)
+                .unwrap_or_else(|_| panic!("no line starting with {:?} in {:?}", prefix, lines));
+            let done = line.starts_with(prefix);
+            lines.push(line);
+            if done {
+                return lines;
+            }
+        }
+    }
+
+    // Send a command and collect its output, isready marks where it ends
+    fn query(&mut self, command: &str) -> Vec<String> {
+        self.send(command);
+        self.send("isready");
+        let mut lines = self.read_until("readyok");
+        lines.pop();
+        lines
+    }
+
+    // Close stdin like a GUI going away and collect what's left until exit
+    fn finish(mut self) -> (Vec<String>, bool) {
+        self.stdin = None;
+        let lines = self.lines.iter().collect();
+        let status = self.child.wait().unwrap();
+        (lines, status.success())
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn resign_is_signaled_in_a_lost_position() {
+    let mut engine = Engine::new();
+    engine.send("setoption name ResignThreshold value 500");
+    engine.send("position fen 7k/8/8/8/8/8/qq6/6K1 w - - 0 1");
+    let mut lines = Vec::new();
+    for _ in 0..3 {
+        engine.send("go depth 2");
+        lines.extend(engine.read_until("bestmove"));
+    }
+    assert!(lines.iter().any(|line| line == "info string resign"));
+}
+
+#[test]
+fn resign_is_off_by_default() {
+    let mut engine = Engine::new();
+    engine.send("position fen 7k/8/8/8/8/8/qq6/6K1 w - - 0 1");
+    let mut lines = Vec::new();
+    for _ in 0..3 {
+        engine.send("go depth 2");
+        lines.extend(engine.read_until("bestmove"));
+    }
+    assert!(!lines.iter().any(|line| line == "info string resign"));
+}
+
+// The first move of the pv on the last info depth line and the bestmove
+fn last_pv_and_bestmove(lines: &[String]) -> (String, String) {
+    let pv = lines
+        .iter()
+        .rev()
+        .find(|line| line.starts_with("info depth"))
+        .and_then(|line| line.split(" pv ").nth(1))
+        .and_then(|pv| pv.split_whitespace().next())
+        .expect("an info depth line with a pv")
+        .to_string();
+    let bestmove = lines
+        .last()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap()
+        .to_string();
+    (pv, bestmove)
+}
+
+#[test]
+fn last_reported_pv_is_the_bestmove() {
+    let mut engine = Engine::new();
+    engine.send("position fen r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4");
+    engine.send("go depth 4");
+    let (pv, bestmove) = last_pv_and_bestmove(&engine.read_until("bestmove"));
+    assert_eq!(pv, bestmove);
+}
+
+#[test]
+fn infinite_analysis_ends_on_its_reported_pv() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4 e7e5 g1f3");
+    engine.send("go infinite");
+    let mut lines = engine.read_until("info depth 3");
+    engine.send("stop");
+    lines.extend(engine.read_until("bestmove"));
+    let (pv, bestmove) = last_pv_and_bestmove(&lines);
+    assert_eq!(pv, bestmove);
+}
+
+#[test]
+fn qsearch_checks_is_an_option() {
+    let mut engine = Engine::new();
+    let options = engine.query("uci");
+    assert!(options.contains(&"option name QSearchChecks type check default false".to_string()));
+    let replies = engine.query("setoption name QSearchChecks value true");
+    assert!(replies.is_empty(), "{:?}", replies);
+    engine.send("position startpos moves e2e4 d7d5");
+    engine.send("go depth 3");
+    let lines = engine.read_until("bestmove");
+    assert!(lines.last().unwrap().starts_with("bestmove "));
+}
+
+// A numeric field of an info line, like nodes or time
+fn info_field(line: &str, name: &str) -> Option<u64> {
+    let mut tokens = line.split_whitespace();
+    tokens.find(|&token| token == name)?;
+    tokens.next()?.parse().ok()
+}
+
+#[test]
+fn ponderhit_carries_on_with_the_ponder_search() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4 e7e5");
+    engine.send("go ponder movetime 200");
+    // Counted from when the search is known to run, a slow start would eat into it
+    let mut lines = engine.read_until("info depth 1");
+    thread::sleep(Duration::from_millis(500));
+    engine.send("ponderhit");
+    lines.extend(engine.read_until("bestmove"));
+    let infos: Vec<&String> = lines
+        .iter()
+        .filter(|line| line.starts_with("info depth"))
+        .collect();
+
+    // Depths, nodes and time go on from the ponder search, nothing restarts
+    // at ponderhit
+    for pair in infos.windows(2) {
+        for name in ["depth", "nodes", "time"] {
+            assert!(
+                info_field(pair[0], name) <= info_field(pair[1], name),
+                "{:?}",
+                pair
+            );
+        }
+    }
+
+    // The movetime counts from ponderhit, the search's time from go
+    let stats = engine.query("stats json").join("");
+    let time: u64 = stats
+        .split("\"time\":")
+        .nth(1)
+        .and_then(|rest| rest.split(',').next())
+        .and_then(|time| time.parse().ok())
+        .unwrap();
+    assert!(time >= 700, "{}", stats);
+}
+
+#[test]
+fn movetime_is_kept_despite_batched_stop_checks() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4 e7e5 g1f3 b8c6 f1b5");
+    let start = Instant::now();
+    engine.send("go movetime 300");
+    engine.read_until("bestmove");
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(600), "{:?}", elapsed);
+}
+
+#[test]
+fn contempt_is_an_option() {
+    let mut engine = Engine::new();
+    let options = engine.query("uci");
+    assert!(options
+        .iter()
+        .any(|line| line.starts_with("option name Contempt type spin default 0")));
+    assert!(engine.query("setoption name Contempt value 30").is_empty());
+    assert_eq!(
+        engine.query("setoption name Contempt value 1000"),
+        ["info string invalid Contempt value 1000"]
+    );
+}
+
+#[test]
+fn eval_benchmark_reports_a_rate() {
+    let mut engine = Engine::new();
+    let lines = engine.query("bench eval 2000");
+    let rate: f64 = lines
+        .iter()
+        .find_map(|line| line.strip_prefix("Evaluations/sec  : "))
+        .and_then(|rate| rate.parse().ok())
+        .unwrap_or_else(|| panic!("no rate in {:?}", lines));
+    assert!(rate > 0.0);
+    assert!(lines.contains(&"Evaluations      : 2000".to_string()));
+}
+
+// The FEN the d command shows for the current position
+fn displayed_fen(engine: &mut Engine) -> String {
+    let lines = engine.query("d");
+    lines
+        .iter()
+        .find_map(|line| line.strip_prefix("FEN          : "))
+        .unwrap_or_else(|| panic!("no FEN in {:?}", lines))
+        .to_string()
+}
+
+#[test]
+fn bare_position_startpos_resets_the_board() {
+    let mut engine = Engine::new();
+    let start = displayed_fen(&mut engine);
+    engine.send("position startpos moves e2e4 e7e5 g1f3");
+    assert_ne!(displayed_fen(&mut engine), start);
+    engine.send("position startpos");
+    assert_eq!(displayed_fen(&mut engine), start);
+    assert_eq!(
+        start,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+    );
+}
+
+#[test]
+fn losing_side_finds_the_perpetual_check() {
+    let mut engine = Engine::new();
+    // Two queens down and mated on b1 next move, unless Qd8+ Kh7 Qh4+ Kg8
+    // goes on forever
+    engine.send("position fen 6k1/5pp1/8/8/7Q/8/qq4PP/7K w - - 0 1");
+    engine.send("go depth 6");
+    let lines = engine.read_until("bestmove");
+    let last_info = lines
+        .iter()
+        .rev()
+        .find(|line| line.starts_with("info depth"))
+        .unwrap();
+    assert!(last_info.contains(" score cp 0 "), "{}", last_info);
+    assert!(lines.last().unwrap().starts_with("bestmove h4d8"));
+}
+
+#[test]
+fn stats_json_has_the_expected_keys() {
+    let mut engine = Engine::new();
+    assert_eq!(
+        engine.query("stats json"),
+        ["info string no search statistics yet"]
+    );
+    engine.send("position startpos moves d2d4");
+    engine.send("go depth 3");
+    let lines = engine.read_until("bestmove");
+    let pv: Vec<String> = lines
+        .iter()
+        .rev()
+        .find(|line| line.starts_with("info depth"))
+        .and_then(|line| line.split(" pv ").nth(1))
+        .unwrap()
+        .split_whitespace()
+        .map(|mv| format!("\"{}\"", mv))
+        .collect();
+    // The whole line the info pv shows, not just its first move
+    assert!(pv.len() > 1, "{:?}", lines);
+
+    let json = engine.query("stats json").join("");
+    assert!(json.starts_with('{') && json.ends_with('}'), "{}", json);
+    let keys: Vec<&str> = json[1..json.len() - 1]
+        .split(',')
+        .filter_map(|field| field.split_once(':'))
+        .map(|(key, _)| key)
+        .collect();
+    for key in [
+        "depth",
+        "seldepth",
+        "nodes",
+        "qnodes",
+        "nps",
+        "time",
+        "tt_hit_rate",
+        "cutoff_rate",
+        "pv",
+    ] {
+        assert!(keys.contains(&format!("\"{}\"", key).as_str()), "{}", json);
+    }
+    assert!(json.contains("\"depth\":3,"), "{}", json);
+    assert!(
+        json.ends_with(&format!("\"pv\":[{}]}}", pv.join(","))),
+        "{}",
+        json
+    );
+}
+
+#[test]
+fn equal_moves_are_chosen_the_same_way_every_run() {
+    // Mirrored wings, the engine has to pick between equal moves
+    let search = |engine: &mut Engine| {
+        engine.send("position fen 4k3/pp4pp/8/8/8/8/PP4PP/4K3 w - - 0 1");
+        engine.send("go depth 3");
+        engine.read_until("bestmove").pop().unwrap()
+    };
+    let mut engine = Engine::new();
+    let first = search(&mut engine);
+    engine.send("ucinewgame");
+    assert_eq!(search(&mut engine), first);
+    assert_eq!(search(&mut Engine::new()), first);
+}
+
+// Nodes and bestmove of a fixed-depth search, after the given setoption commands
+fn search_nodes(depth: u32, options: &[&str]) -> (u64, String) {
+    search_nodes_from(
+        "startpos moves e2e4 e7e5 g1f3 b8c6 f1c4 g8f6",
+        depth,
+        options,
+    )
+}
+
+fn search_nodes_from(position: &str, depth: u32, options: &[&str]) -> (u64, String) {
+    let mut engine = Engine::new();
+    for option in options {
+        assert!(engine.query(option).is_empty(), "{}", option);
+    }
+    engine.send(&format!("position {}", position));
+    engine.send(&format!("go depth {}", depth));
+    let lines = engine.read_until("bestmove");
+    let nodes = lines
+        .iter()
+        .rev()
+        .find(|line| line.starts_with("info depth"))
+        .and_then(|line| info_field(line, "nodes"))
+        .unwrap();
+    (nodes, lines.last().unwrap().clone())
+}
+
+#[test]
+fn pruning_parameters_change_the_search() {
+    let (default, _) = search_nodes(5, &[]);
+    assert_eq!(
+        search_nodes(5, &["setoption name NullMoveMinDepth value 3"]).0,
+        default
+    );
+    for option in [
+        "setoption name NullMoveMinDepth value 64",
+        "setoption name NullMoveReduction value 0",
+        "setoption name LmrMinDepth value 64",
+        "setoption name LmrMinMoves value 1",
+    ] {
+        assert_ne!(search_nodes(5, &[option]).0, default, "{}", option);
+    }
+}
+
+#[test]
+fn null_move_pruning_saves_nodes_but_keeps_the_move() {
+    let (pruned, pruned_move) = search_nodes(5, &[]);
+    let (full, full_move) = search_nodes(5, &["setoption name NullMoveMinDepth value 64"]);
+    assert!(pruned < full, "{} against {}", pruned, full);
+    assert_eq!(pruned_move, full_move);
+    // Analysis doesn't prune
+    let (analysed, _) = search_nodes(
+        4,
+        &[
+            "setoption name UCI_AnalyseMode value true",
+            "setoption name LmrMinDepth value 64",
+        ],
+    );
+    assert_eq!(
+        analysed,
+        search_nodes(
+            4,
+            &[
+                "setoption name UCI_AnalyseMode value true",
+                "setoption name LmrMinDepth value 64",
+                "setoption name NullMoveMinDepth value 64",
+            ]
+        )
+        .0
+    );
+}
+
+#[test]
+fn late_move_reductions_save_nodes_and_leave_perft_alone() {
+    let (reduced, _) = search_nodes(5, &[]);
+    let (full, _) = search_nodes(5, &["setoption name LmrMinDepth value 64"]);
+    assert!(reduced < full, "{} against {}", reduced, full);
+
+    // Move generation doesn't know about the search
+    let perft = |options: &[&str]| {
+        let mut engine = Engine::new();
+        for option in options {
+            engine.send(option);
+        }
+        engine
+            .query("perft 3")
+            .into_iter()
+            .filter(|line| !line.starts_with("Total time") && !line.starts_with("Nodes/sec"))
+            .collect::<Vec<String>>()
+    };
+    let counts = perft(&[]);
+    assert!(counts.contains(&"Depth 3: 8902".to_string()));
+    assert_eq!(counts, perft(&["setoption name LmrMinDepth value 64"]));
+
+    // Analysis doesn't reduce
+    let (analysed, _) = search_nodes(
+        4,
+        &[
+            "setoption name UCI_AnalyseMode value true",
+            "setoption name LmrMinMoves value 1",
+        ],
+    );
+    assert_eq!(
+        analysed,
+        search_nodes(
+            4,
+            &[
+                "setoption name UCI_AnalyseMode value true",
+                "setoption name LmrMinDepth value 64",
+            ]
+        )
+        .0
+    );
+}
+
+#[test]
+fn analyse_mode_searches_more_nodes() {
+    let (pruned, _) = search_nodes(4, &[]);
+    let (analysed, _) = search_nodes(4, &["setoption name UCI_AnalyseMode value true"]);
+    assert!(analysed > pruned, "{} against {}", analysed, pruned);
+}
+
+#[test]
+fn san_and_long_algebraic_moves_reach_the_same_board() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e4 e5 Nf3 Nc6 Bb5 a6 O-O");
+    let san = displayed_fen(&mut engine);
+    engine.send("position startpos moves e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 e1g1");
+    assert_eq!(displayed_fen(&mut engine), san);
+    assert_ne!(
+        san,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+    );
+}
+
+#[test]
+fn combined_limits_stop_at_whichever_comes_first() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves d2d4 d7d5 c2c4");
+    let last_depth = |lines: &[String]| {
+        lines
+            .iter()
+            .rev()
+            .find(|line| line.starts_with("info depth"))
+            .and_then(|line| info_field(line, "depth"))
+            .unwrap()
+    };
+
+    let start = Instant::now();
+    engine.send("go depth 30 movetime 100");
+    let lines = engine.read_until("bestmove");
+    assert!(
+        start.elapsed() < Duration::from_millis(400),
+        "{:?}",
+        start.elapsed()
+    );
+    assert!(last_depth(&lines) < 30);
+
+    let start = Instant::now();
+    engine.send("go depth 2 movetime 60000");
+    let lines = engine.read_until("bestmove");
+    assert!(
+        start.elapsed() < Duration::from_secs(10),
+        "{:?}",
+        start.elapsed()
+    );
+    assert_eq!(last_depth(&lines), 2);
+}
+
+#[test]
+fn white_perspective_scores_black_wins_below_zero() {
+    let score = |options: &[&str]| {
+        let mut engine = Engine::new();
+        for option in options {
+            engine.send(option);
+        }
+        // Black to move mates with Qg2 or Qf1
+        engine.send("position fen 8/8/8/8/8/6k1/5q2/7K b - - 0 1");
+        engine.send("go depth 2");
+        let lines = engine.read_until("bestmove");
+        let info = lines
+            .iter()
+            .rev()
+            .find(|line| line.starts_with("info depth"))
+            .unwrap();
+        let score = info.split(" score ").nth(1).unwrap();
+        score.split(" nodes").next().unwrap().to_string()
+    };
+    assert_eq!(score(&[]), "mate 1");
+    assert_eq!(
+        score(&["setoption name ScorePerspective value White"]),
+        "mate -1"
+    );
+}
+
+#[test]
+fn transposed_move_orders_print_the_same_hash() {
+    let mut engine = Engine::new();
+    let mut hash = |moves: &str| {
+        engine.send(&format!("position startpos moves {}", moves));
+        engine.query("hash")
+    };
+    let knights_first = hash("g1f3 g8f6 e2e4");
+    assert_eq!(knights_first.len(), 1);
+    assert!(knights_first[0].starts_with("info string hash "));
+    assert_eq!(knights_first, hash("e2e4 g8f6 g1f3"));
+    // The same pieces with the other side to move
+    assert_ne!(knights_first, hash("g1f3 g8f6 e2e3 b8c6 e3e4 c6b8"));
+}
+
+#[test]
+fn node_stats_split_the_total_between_main_and_quiescence() {
+    let mut engine = Engine::new();
+    engine.send("setoption name NodeStats value true");
+    engine.send("position startpos moves e2e4 e7e5 g1f3 b8c6");
+    engine.send("go movetime 500");
+    let lines = engine.read_until("bestmove");
+    let stats: Vec<&String> = lines
+        .iter()
+        .filter(|line| line.starts_with("info string nodes"))
+        .collect();
+    assert!(stats.len() >= 2, "{:?}", lines);
+
+    let mut iterations = 0;
+    for line in stats {
+        let total = info_field(line, "total").unwrap();
+        let main = info_field(line, "main").unwrap();
+        let quiescence = info_field(line, "quiescence").unwrap();
+        assert_eq!(main + quiescence, total, "{}", line);
+        assert!(quiescence > 0, "{}", line);
+        iterations += info_field(line, "iteration").unwrap();
+        assert_eq!(iterations, total, "{}", line);
+    }
+}
+
+#[test]
+fn no_time_left_still_gets_a_legal_move_at_once() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4 e7e5");
+    engine.send("isready");
+    engine.read_until("readyok");
+    let start = Instant::now();
+    // Both clocks are under the safeguard, so there's no time to search at all
+    engine.send("go wtime 10 btime 10");
+    let lines = engine.read_until("bestmove");
+    assert!(
+        start.elapsed() < Duration::from_millis(200),
+        "{:?}",
+        start.elapsed()
+    );
+    assert!(!lines.iter().any(|line| line.starts_with("info depth")));
+
+    let bestmove = lines.last().unwrap().split_whitespace().nth(1).unwrap();
+    let before = displayed_fen(&mut engine);
+    let lines = engine.query(&format!("position startpos moves e2e4 e7e5 {}", bestmove));
+    assert!(lines.is_empty(), "{:?}", lines);
+    assert_ne!(displayed_fen(&mut engine), before);
+}
+
+#[test]
+fn debug_mode_warns_about_a_hanging_best_move() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4 e7e5");
+    // Limiting the root to Ba6 stands in for a broken eval that likes it
+    let blunder = |engine: &mut Engine| {
+        engine.send("go depth 2 searchmoves f1a6");
+        engine.read_until("bestmove")
+    };
+    let quiet = blunder(&mut engine);
+    assert!(!quiet
+        .iter()
+        .any(|line| line.starts_with("info string warning")));
+
+    engine.send("debug on");
+    let lines = blunder(&mut engine);
+    assert_eq!(lines.last().unwrap(), "bestmove f1a6");
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.starts_with("info string warning f1a6 hangs the piece on a6")),
+        "{:?}",
+        lines
+    );
+}
+
+#[test]
+fn ponder_miss_drops_the_ponder_search() {
+    let mut engine = Engine::new();
+    // Pondering on 1... e5, but the opponent answers 1... c5
+    engine.send("position startpos moves e2e4 e7e5");
+    engine.send("go ponder wtime 60000 btime 60000");
+    thread::sleep(Duration::from_millis(300));
+    engine.send("position startpos moves e2e4 c7c5");
+    engine.send("go depth 3");
+    let lines = engine.read_until("bestmove");
+    let bestmove = lines.last().unwrap().split_whitespace().nth(1).unwrap();
+
+    // The ponder search never answers, before the new one or after it
+    engine.send("isready");
+    let later = engine.read_until("readyok");
+    assert!(
+        !later.iter().any(|line| line.starts_with("bestmove")),
+        "{:?}",
+        later
+    );
+    let bestmoves = lines
+        .iter()
+        .filter(|line| line.starts_with("bestmove"))
+        .count();
+    assert_eq!(bestmoves, 1, "{:?}", lines);
+
+    let check = engine.query(&format!("position startpos moves e2e4 c7c5 {}", bestmove));
+    assert!(check.is_empty(), "{} in {:?}", bestmove, check);
+}
+
+#[test]
+fn opening_ordering_can_be_measured_on_and_off() {
+    let (on, on_move) = search_nodes(4, &[]);
+    let (off, off_move) = search_nodes(4, &["setoption name OpeningOrdering value false"]);
+    // The rules only order moves, so they change the work but not what's legal
+    assert_ne!(on, off, "the same {} nodes either way", on);
+    for bestmove in [&on_move, &off_move] {
+        let mut engine = Engine::new();
+        let mv = bestmove.split_whitespace().nth(1).unwrap();
+        let lines = engine.query(&format!(
+            "position startpos moves e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 {}",
+            mv
+        ));
+        assert!(lines.is_empty(), "{} {:?}", bestmove, lines);
+    }
+}
+
+#[test]
+fn command_log_records_commands_verbatim() {
+    let path = std::env::temp_dir().join(format!("sappyduck-log-{}.txt", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut engine = Engine::new();
+    engine.send(&format!(
+        "setoption name CommandLog value {}",
+        path.display()
+    ));
+    engine.send("position startpos moves e2e4  e7e5");
+    engine.send("go depth 1");
+    engine.read_until("bestmove");
+    engine.query("setoption name CommandLog value");
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let lines: Vec<&str> = log.lines().collect();
+    // Received lines keep their spacing, sent lines are marked the other way
+    assert_eq!(lines[0], "> position startpos moves e2e4  e7e5");
+    assert_eq!(lines[1], "> go depth 1");
+    assert!(lines.iter().any(|line| line.starts_with("< bestmove ")));
+    assert_eq!(lines.last(), Some(&"> setoption name CommandLog value"));
+}
+
+#[test]
+fn won_quiet_positions_are_searched_with_fewer_nodes() {
+    // A queen and two rooks up, nothing to take
+    let won = "fen 4k3/8/8/8/8/8/1Q6/R3K2R w - - 0 1";
+    let (fast, fast_move) = search_nodes_from(won, 5, &[]);
+    let (full, full_move) = search_nodes_from(won, 5, &["setoption name FastTrackMargin value 0"]);
+    assert!(fast < full, "{} against {}", fast, full);
+    for bestmove in [&fast_move, &full_move] {
+        let mut engine = Engine::new();
+        let mv = bestmove.split_whitespace().nth(1).unwrap();
+        let lines = engine.query(&format!("position {} moves {}", won, mv));
+        assert!(lines.is_empty(), "{} {:?}", bestmove, lines);
+    }
+}
+
+#[test]
+fn bad_position_input_is_reported() {
+    let mut engine = Engine::new();
+    assert_eq!(
+        engine.query("position startpos moves e2e4 e2e4 d7d5"),
+        ["info string illegal move e2e4, ignoring the rest"]
+    );
+    assert_eq!(
+        displayed_fen(&mut engine),
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+    );
+    assert_eq!(
+        engine.query("position startpos moves Zz9"),
+        ["info string malformed move Zz9, ignoring the rest"]
+    );
+    let lines = engine.query("position fen 4k3/8/8/8/8/8/8/4K3 w - e4 0 1");
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.contains("invalid fen en passant square: \"e4\"")),
+        "{:?}",
+        lines
+    );
+}
+
+#[test]
+fn go_depth_reports_every_depth_up_to_the_one_asked_for() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4 e7e5");
+    engine.send("go depth 5");
+    let lines = engine.read_until("bestmove");
+    let depths: Vec<u64> = lines
+        .iter()
+        .filter(|line| line.starts_with("info depth"))
+        .filter_map(|line| info_field(line, "depth"))
+        .collect();
+    assert_eq!(depths, [1, 2, 3, 4, 5]);
+    let (pv, bestmove) = last_pv_and_bestmove(&lines);
+    assert_eq!(pv, bestmove);
+}
+
+#[test]
+fn short_movetime_keeps_to_time_and_nodes_deepen_past_depth_one() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4 e7e5");
+    engine.send("isready");
+    engine.read_until("readyok");
+    let start = Instant::now();
+    engine.send("go movetime 100");
+    let lines = engine.read_until("bestmove");
+    let elapsed = start.elapsed();
+    assert!(elapsed < Duration::from_millis(400), "{:?}", elapsed);
+    let depths: Vec<u64> = lines
+        .iter()
+        .filter(|line| line.starts_with("info depth"))
+        .filter_map(|line| info_field(line, "depth"))
+        .collect();
+    assert_eq!(depths.first(), Some(&1));
+
+    let bestmove = lines.last().unwrap().split_whitespace().nth(1).unwrap();
+    let lines = engine.query(&format!("position startpos moves e2e4 e7e5 {}", bestmove));
+    assert!(lines.is_empty(), "{:?}", lines);
+
+    // Given the budget the search goes on deepening, counted in nodes so it
+    // doesn't depend on the machine's speed
+    engine.send("go nodes 20000");
+    let depths: Vec<u64> = engine
+        .read_until("bestmove")
+        .iter()
+        .filter(|line| line.starts_with("info depth"))
+        .filter_map(|line| info_field(line, "depth"))
+        .collect();
+    assert!(depths.last() > Some(&1), "{:?}", depths);
+}
+
+#[test]
+fn node_counts_add_up_over_the_whole_go() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4 e7e5 g1f3");
+    engine.send("go depth 4");
+    let lines = engine.read_until("bestmove");
+    let infos: Vec<&String> = lines
+        .iter()
+        .filter(|line| line.starts_with("info depth"))
+        .collect();
+    let nodes: Vec<u64> = infos
+        .iter()
+        .map(|line| info_field(line, "nodes").unwrap())
+        .collect();
+    assert!(nodes[0] > 0);
+    // Each depth carries on from the counts of the ones before
+    assert!(
+        nodes.windows(2).all(|pair| pair[0] < pair[1]),
+        "{:?}",
+        nodes
+    );
+    assert!(infos.iter().all(|line| info_field(line, "nps").is_some()));
+}
+
+#[test]
+fn narrow_aspiration_windows_survive_a_score_swing() {
+    // Material at first, then the smothered mate shows up: Nh6+ Kh8 Qg8+ Rxg8 Nf7#
+    let mut engine = Engine::new();
+    engine.send("setoption name AspirationWindow value 1");
+    engine.send("setoption name AspirationMinDepth value 1");
+    engine.send("position fen 5rk1/5Npp/8/3Q4/8/8/8/6K1 w - - 0 1");
+    engine.send("go depth 5 nodes 10000000");
+    let lines = engine.read_until("bestmove");
+    let (pv, bestmove) = last_pv_and_bestmove(&lines);
+    assert_eq!((pv.as_str(), bestmove.as_str()), ("f7h6", "f7h6"));
+    let last_info = lines
+        .iter()
+        .rev()
+        .find(|line| line.starts_with("info depth"))
+        .unwrap();
+    assert!(last_info.contains(" score mate 3 "), "{}", last_info);
+}
+
+// The first pv move of every multipv line of the last depth, in order
+fn multipv_moves(lines: &[String]) -> Vec<String> {
+    let last_depth = lines
+        .iter()
+        .filter(|line| line.starts_with("info depth"))
+        .filter_map(|line| info_field(line, "depth"))
+        .max()
+        .unwrap();
+    lines
+        .iter()
+        .filter(|line| info_field(line, "depth") == Some(last_depth))
+        .filter_map(|line| line.split(" pv ").nth(1))
+        .filter_map(|pv| pv.split_whitespace().next())
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn multipv_reports_distinct_lines_up_to_the_legal_moves() {
+    let mut engine = Engine::new();
+    engine.send("setoption name MultiPV value 3");
+    engine.send("position startpos");
+    engine.send("go depth 3");
+    let lines = engine.read_until("bestmove");
+    let moves = multipv_moves(&lines);
+    assert_eq!(moves.len(), 3, "{:?}", moves);
+    assert!(moves[0] != moves[1] && moves[1] != moves[2] && moves[0] != moves[2]);
+    let (_, bestmove) = last_pv_and_bestmove(&lines);
+    assert_eq!(bestmove, moves[0]);
+
+    // Only Kb1 is legal, so there's only one line to show
+    engine.send("position fen 7k/8/8/8/8/8/7r/K7 w - - 0 1");
+    engine.send("go depth 3");
+    let lines = engine.read_until("bestmove");
+    assert_eq!(multipv_moves(&lines), ["a1b1"]);
+}
+
+#[test]
+fn movetime_takes_precedence_over_the_clock() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4 e7e5");
+    engine.send("isready");
+    engine.read_until("readyok");
+    let start = Instant::now();
+    // The clocks alone would allow several seconds
+    engine.send("go wtime 600000 btime 600000 movetime 200");
+    engine.read_until("bestmove");
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(400), "{:?}", elapsed);
+}
+
+#[test]
+fn go_mate_solves_a_mate_in_two() {
+    let mut engine = Engine::new();
+    // Re8+ Rxe8 Rxe8#
+    engine.send("position fen 3r2k1/5ppp/8/8/8/8/4R3/4R1K1 w - - 0 1");
+    engine.send("go mate 2");
+    let lines = engine.read_until("bestmove");
+    let (pv, bestmove) = last_pv_and_bestmove(&lines);
+    assert_eq!((pv.as_str(), bestmove.as_str()), ("e2e8", "e2e8"));
+    let last_info = lines
+        .iter()
+        .rev()
+        .find(|line| line.starts_with("info depth"))
+        .unwrap();
+    assert!(last_info.contains(" score mate 2 "), "{}", last_info);
+
+    // Without a mate in reach it still ends with a legal move
+    engine.send("position startpos");
+    engine.send("go mate 1");
+    let bestmove = engine.read_until("bestmove").pop().unwrap();
+    let mv = bestmove.split_whitespace().nth(1).unwrap();
+    let lines = engine.query(&format!("position startpos moves {}", mv));
+    assert!(lines.is_empty(), "{:?}", lines);
+}
+
+#[test]
+fn searchmoves_limits_the_root_and_skips_illegal_moves() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4 e7e5");
+    for go in ["go depth 3", "go nodes 20000"] {
+        engine.send(&format!("{} searchmoves h2h3 e2e5 xyz", go));
+        let lines = engine.read_until("bestmove");
+        assert!(
+            lines.last().unwrap().starts_with("bestmove h2h3"),
+            "{:?}",
+            lines
+        );
+        for skipped in ["e2e5", "xyz"] {
+            let warning = format!("info string searchmoves: {} is not a legal move", skipped);
+            assert!(
+                lines.iter().any(|line| line.starts_with(&warning)),
+                "{:?}",
+                lines
+            );
+        }
+    }
+}
+
+#[test]
+fn timed_bestmove_lines_carry_only_the_moves() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4 e7e5");
+    engine.send("go movetime 100");
+    let lines = engine.read_until("bestmove");
+    let tokens: Vec<&str> = lines.last().unwrap().split_whitespace().collect();
+    assert!(
+        tokens.len() == 2 || (tokens.len() == 4 && tokens[2] == "ponder"),
+        "{:?}",
+        tokens
+    );
+    // The time spent comes just before, as an info string
+    let before = &lines[lines.len() - 2];
+    assert!(before.starts_with("info string time spent "), "{}", before);
+}
+
+#[test]
+fn position_fen_takes_an_optional_move_list() {
+    let mut engine = Engine::new();
+    let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+    engine.send(&format!("position fen {}", fen));
+    assert_eq!(displayed_fen(&mut engine), fen);
+
+    engine.send(&format!("position fen {} moves f1b5 a7a6", fen));
+    assert_eq!(
+        displayed_fen(&mut engine),
+        "r1bqkbnr/1ppp1ppp/p1n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 0 4"
+    );
+
+    // The same moves from the start position end up in the same place
+    engine.send("position startpos moves e2e4 e7e5 g1f3 b8c6 f1b5 a7a6");
+    assert_eq!(
+        displayed_fen(&mut engine),
+        "r1bqkbnr/1ppp1ppp/p1n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 0 4"
+    );
+}
+
+#[test]
+fn hashfull_rises_during_a_search_and_starts_over_after_ucinewgame() {
+    let hashfull = |lines: &[String]| -> Vec<u64> {
+        lines
+            .iter()
+            .filter(|line| line.starts_with("info depth"))
+            .filter_map(|line| info_field(line, "hashfull"))
+            .collect()
+    };
+    let mut engine = Engine::new();
+    engine.send("setoption name Hash value 1");
+    engine.send("position startpos moves e2e4 e7e5 g1f3 b8c6");
+    // A node budget, the same fill on any machine
+    engine.send("go nodes 100000");
+    let filled = hashfull(&engine.read_until("bestmove"));
+    assert!(
+        filled.windows(2).all(|pair| pair[0] <= pair[1]),
+        "{:?}",
+        filled
+    );
+    assert!(filled.last() > filled.first(), "{:?}", filled);
+
+    engine.send("ucinewgame");
+    engine.send("position startpos moves e2e4 e7e5 g1f3 b8c6");
+    engine.send("go depth 1");
+    let fresh = hashfull(&engine.read_until("bestmove"));
+    assert!(
+        fresh[0] < *filled.last().unwrap(),
+        "{:?} {:?}",
+        fresh,
+        filled
+    );
+}
+
+#[test]
+fn end_of_input_exits_cleanly() {
+    // A command file without quit at the end
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4");
+    engine.send("d");
+    let (lines, success) = engine.finish();
+    assert!(success);
+    assert!(
+        lines.iter().any(|line| line.starts_with("FEN ")),
+        "{:?}",
+        lines
+    );
+
+    // Also in the middle of a search, which still answers first
+    let mut engine = Engine::new();
+    engine.send("position startpos");
+    engine.send("go infinite");
+    engine.read_until("info depth 1");
+    let (lines, success) = engine.finish();
+    assert!(success);
+    assert!(
+        lines
+            .last()
+            .is_some_and(|line| line.starts_with("bestmove ")),
+        "{:?}",
+        lines
+    );
+}
+
+#[test]
+fn ucinewgame_forgets_the_stored_entries() {
+    let mut engine = Engine::new();
+    let search = |engine: &mut Engine| {
+        engine.send("position startpos moves e2e4 e7e5 g1f3 b8c6");
+        engine.send("go depth 5");
+        let lines = engine.read_until("bestmove");
+        lines
+            .iter()
+            .rev()
+            .find(|line| line.starts_with("info depth"))
+            .and_then(|line| info_field(line, "nodes"))
+            .unwrap()
+    };
+    let fresh = search(&mut engine);
+    // Searching again lives off what the first search stored
+    assert!(search(&mut engine) < fresh);
+    // After ucinewgame there's nothing left to live off
+    engine.send("ucinewgame");
+    assert_eq!(search(&mut engine), fresh);
+}
+
+#[test]
+fn the_shortest_mate_is_reported() {
+    // Re8# now, or a slower mate after almost any other rook move
+    let mut engine = Engine::new();
+    engine.send("position fen 6k1/5ppp/8/8/8/8/4R3/4R1K1 w - - 0 1");
+    engine.send("go depth 5");
+    let lines = engine.read_until("bestmove");
+    assert_eq!(lines.last().unwrap(), "bestmove e2e8");
+    let last_info = lines
+        .iter()
+        .rev()
+        .find(|line| line.starts_with("info depth"))
+        .unwrap();
+    assert!(last_info.contains(" score mate 1 "), "{}", last_info);
+    assert!(last_info.ends_with(" pv e2e8"), "{}", last_info);
+}
+
+#[test]
+fn infinite_search_waits_for_stop_even_when_done() {
+    let mut engine = Engine::new();
+    // Bare kings, every depth is over at once
+    engine.send("position fen 7k/8/8/8/8/8/8/K7 w - - 0 1");
+    engine.send("go infinite");
+    thread::sleep(Duration::from_millis(300));
+    engine.send("isready");
+    let lines = engine.read_until("readyok");
+    assert!(
+        !lines.iter().any(|line| line.starts_with("bestmove")),
+        "{:?}",
+        lines
+    );
+    engine.send("stop");
+    let bestmove = engine.read_until("bestmove").pop().unwrap();
+    assert!(bestmove.starts_with("bestmove a1"), "{}", bestmove);
+}
+
+#[test]
+fn ponder_with_a_depth_waits_for_ponderhit() {
+    let mut engine = Engine::new();
+    engine.send("position startpos moves e2e4 e7e5");
+    engine.send("go ponder depth 2");
+    thread::sleep(Duration::from_millis(300));
+    engine.send("isready");
+    let lines = engine.read_until("readyok");
+    assert!(
+        !lines.iter().any(|line| line.starts_with("bestmove")),
+        "{:?}",
+        lines
+    );
+    engine.send("ponderhit");
+    engine.read_until("bestmove");
+}
+
+#[test]
+fn go_depth_is_clamped_to_the_deepest_iteration() {
+    let mut engine = Engine::new();
+    engine.send("position startpos");
+    engine.send("go depth 1000");
+    let lines = engine.read_until("info string starting analysis");
+    assert!(
+        lines.last().unwrap().ends_with("at depth 64"),
+        "{:?}",
+        lines
+    );
+    engine.send("stop");
+    engine.read_until("bestmove");
+}
+
+#[test]
+fn hash_change_ends_a_running_search_first() {
+    let mut engine = Engine::new();
+    engine.send("position startpos");
+    engine.send("go infinite");
+    engine.read_until("info depth 1 ");
+    engine.send("setoption name Hash value 2");
+    engine.read_until("bestmove");
+    engine.send("go depth 3");
+    engine.read_until("bestmove");
+}